@@ -1,8 +1,9 @@
 use crate::base58_check;
+use crate::script::{self, ScriptType};
 use crate::{Error, TryInto};
 use std::{error, fmt};
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone)]
 pub enum Address {
     P2PKH([u8; 20]),
     P2SH([u8; 20]),
@@ -11,13 +12,11 @@ pub enum Address {
 impl Address {
     #[inline(always)]
     pub fn from_script(script: &[u8]) -> Result<Address, Error> {
-        if script.len() == 25 && script[0] == 0x76 && script[1] == 0xA9 && script[2] == 0x14 && script[23] == 0x88 && script[24] == 0xAC {
-            return Ok(Address::P2PKH(script[3..23].try_into().unwrap()));
+        match script::classify(script) {
+            ScriptType::P2PKH => Ok(Address::P2PKH(script[3..23].try_into().unwrap())),
+            ScriptType::P2SH => Ok(Address::P2SH(script[2..22].try_into().unwrap())),
+            _ => Err(Error::new("Invalid address script")),
         }
-        if script.len() == 23 && script[0] == 0xa9 && script[1] == 0x14 && script[22] == 0x87 {
-            return Ok(Address::P2SH(script[2..22].try_into().unwrap()));
-        }
-        Err(Error::new("Invalid address script"))
     }
 
     #[inline(always)]
@@ -41,6 +40,24 @@ impl Address {
         Self::from_slice(&buffer[0..len])
     }
 
+    #[inline(always)]
+    pub fn to_script(&self) -> Vec<u8> {
+        match self {
+            Address::P2PKH(hash) => {
+                let mut script = vec![0x76, 0xA9, 0x14];
+                script.extend_from_slice(hash);
+                script.extend_from_slice(&[0x88, 0xAC]);
+                script
+            }
+            Address::P2SH(hash) => {
+                let mut script = vec![0xA9, 0x14];
+                script.extend_from_slice(hash);
+                script.push(0x87);
+                script
+            }
+        }
+    }
+
     #[inline(always)]
     pub fn to_vec(&self) -> Vec<u8> {
         let (version, hash) = match self {
@@ -0,0 +1,102 @@
+use crate::{Address, IndexedStore};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// Maximum number of addresses kept in the rich list.
+pub const RICH_LIST_SIZE: usize = 1000;
+/// Balance buckets: zero, then one bucket per power of ten satoshis up to the 21M BTC supply.
+const BUCKETS: usize = 17;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceBucket {
+    pub from: u64,
+    pub to: u64,
+    pub addresses: u64,
+    pub balance: u64,
+}
+
+/// Balances of the addresses of the store, computed from a snapshot of its UTXO set.
+#[derive(Debug, Clone)]
+pub struct BalanceStats {
+    pub height: usize,
+    pub hash: [u8; 32],
+    pub rich_list: Vec<(Address, u64)>,
+    pub distribution: Vec<BalanceBucket>,
+}
+
+fn bucket(balance: u64) -> usize {
+    let mut index = 0;
+    let mut bound = 1;
+    while balance >= bound && index < BUCKETS - 1 {
+        bound = bound.saturating_mul(10);
+        index += 1;
+    }
+    index
+}
+
+fn bucket_range(index: usize) -> (u64, u64) {
+    match index {
+        0 => (0, 1),
+        index => (10u64.pow((index - 1) as u32), 10u64.pow(index as u32)),
+    }
+}
+
+impl BalanceStats {
+    pub fn new(store: &IndexedStore) -> Self {
+        let mut rich_list = BinaryHeap::with_capacity(RICH_LIST_SIZE + 1);
+        let mut distribution: Vec<_> = (0..BUCKETS)
+            .map(|index| {
+                let (from, to) = bucket_range(index);
+                BalanceBucket {
+                    from,
+                    to,
+                    addresses: 0,
+                    balance: 0,
+                }
+            })
+            .collect();
+        store.backend().iterate_balances(|address, balance| {
+            let bucket = &mut distribution[bucket(balance)];
+            bucket.addresses += 1;
+            bucket.balance += balance;
+            let is_rich = rich_list.len() < RICH_LIST_SIZE || matches!(rich_list.peek(), Some(Reverse((min, _))) if balance > *min);
+            if is_rich {
+                rich_list.push(Reverse((balance, address.clone())));
+                if rich_list.len() > RICH_LIST_SIZE {
+                    rich_list.pop();
+                }
+            }
+        });
+        let rich_list = rich_list
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((balance, address))| (address, balance))
+            .collect();
+        Self {
+            height: store.height() - 1,
+            hash: store.last_block_hash().unwrap(),
+            rich_list,
+            distribution,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket_range, BUCKETS};
+
+    #[test]
+    fn bucket() {
+        assert_eq!(super::bucket(0), 0);
+        assert_eq!(super::bucket(1), 1);
+        assert_eq!(super::bucket(9), 1);
+        assert_eq!(super::bucket(10), 2);
+        assert_eq!(super::bucket(100_000_000), 9);
+        assert_eq!(super::bucket(21_000_000 * 100_000_000), BUCKETS - 1);
+        assert_eq!(super::bucket(u64::MAX), BUCKETS - 1);
+        for index in 0..BUCKETS {
+            let (from, to) = bucket_range(index);
+            assert_eq!(super::bucket(from), index);
+            assert_eq!(super::bucket(to - 1), index);
+        }
+    }
+}
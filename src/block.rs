@@ -1,31 +1,41 @@
-use crate::{Hasher, HashingBufferReader, Transaction, TryInto};
+use crate::{Hasher, HashingBufferReader, ParseResult, ParseResultExt, Transaction};
 
 #[derive(Debug, Clone)]
 pub struct BlockHeader {
     pub hash: [u8; 32],
     pub previous_block_hash: [u8; 32],
+    pub time: u32,
 }
 
 impl BlockHeader {
-    pub fn from_buffer(buffer: &[u8]) -> Self {
+    /// Parses the header at the start of the buffer, which may hold more data.
+    pub fn from_buffer(buffer: &[u8]) -> ParseResult<Self> {
         let mut hasher = Hasher::new();
         let mut reader = HashingBufferReader::new(buffer);
-        let buffer = reader.read_buffer(80, &mut Some(&mut hasher));
+        reader.read_buffer(80, &mut Some(&mut hasher)).field("block header")?;
         let hash = hasher.digest();
         let mut reader = HashingBufferReader::new(buffer);
-        reader.skip(4, &mut None);
-        let previous_block_hash = reader.read_hash(&mut None);
-        Self { hash, previous_block_hash }
+        reader.skip(4, &mut None)?;
+        let previous_block_hash = reader.read_hash(&mut None).field("previous block hash")?;
+        reader.skip(32, &mut None)?;
+        let time = reader.read_u32_le(&mut None).field("time")?;
+        Ok(Self {
+            hash,
+            previous_block_hash,
+            time,
+        })
     }
 }
 
-pub fn iterate_transactions<F: FnMut(Transaction)>(buffer: &[u8], callback: &mut F) -> () {
-    let mut reader = HashingBufferReader::new(&buffer[80..]);
-    let count: usize = reader.read_var_int_le(&mut None).try_into().unwrap();
+pub fn iterate_transactions<F: FnMut(Transaction)>(buffer: &[u8], callback: &mut F) -> ParseResult<()> {
+    let mut reader = HashingBufferReader::new(buffer);
+    reader.skip(80, &mut None).field("block header")?;
+    let count = reader.read_count_le(1, &mut None).field("transaction count")?;
     for _ in 0..count {
-        let transaction = Transaction::from_reader(&mut reader);
+        let transaction = Transaction::from_reader(&mut reader)?;
         callback(transaction);
     }
+    reader.expect_end("block")
 }
 
 pub trait BlockTrait {
@@ -52,17 +62,17 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn new(buffer: &[u8], height: usize) -> Self {
-        let header = BlockHeader::from_buffer(&buffer);
+    pub fn new(buffer: &[u8], height: usize) -> ParseResult<Self> {
+        let header = BlockHeader::from_buffer(&buffer)?;
         let mut transactions = Vec::new();
         iterate_transactions(&buffer, &mut |transaction| {
             transactions.push(transaction);
-        });
-        Self {
+        })?;
+        Ok(Self {
             height,
             header,
             transactions,
-        }
+        })
     }
 }
 
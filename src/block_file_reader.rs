@@ -1,4 +1,7 @@
-use crate::{iterate_transactions, BlockHeader, BlockTrait, HashMap, HashingBufferReader, Logger, ThreadPool, Transaction, TryInto};
+use crate::{
+    iterate_transactions, BlockHeader, BlockTrait, HashMap, HashingBufferReader, Logger, ParseResult, ParseResultExt, ThreadPool,
+    Transaction, TryInto,
+};
 use regex::Regex;
 use std::{
     fs::{read_dir, File},
@@ -6,6 +9,21 @@ use std::{
     path::Path,
 };
 
+const BLOCK_MAGIC: u32 = 3652501241;
+
+/// Reads the magic and length that start each block of a block file followed by the block header, or `None` where the
+/// node preallocated the file with zeros.
+fn read_block_header(buffer: &[u8]) -> ParseResult<Option<(u32, usize, BlockHeader)>> {
+    let mut reader = HashingBufferReader::new(buffer);
+    let magic = reader.read_u32_le(&mut None).field("magic")?;
+    if magic == 0 {
+        return Ok(None);
+    }
+    let length = reader.read_u32_le(&mut None).field("block length")?.try_into().unwrap();
+    let header = BlockHeader::from_buffer(&buffer[8..])?;
+    Ok(Some((magic, length, header)))
+}
+
 #[derive(Debug, Clone)]
 pub struct FileBlock {
     file_path: String,
@@ -29,9 +47,15 @@ impl BlockTrait for FileBlock {
         file.seek(SeekFrom::Start(self.offset)).unwrap();
         let mut buffer = vec![0; self.length];
         file.read_exact(&mut buffer).unwrap();
-        iterate_transactions(&buffer, &mut |transaction| {
+        // Blocks of the longest chain were accepted by the node, failing to parse one means the block files are corrupted.
+        if let Err(error) = iterate_transactions(&buffer, &mut |transaction| {
             callback(&transaction);
-        });
+        }) {
+            panic!(
+                "Could not parse block {} at offset {} of {}: {}",
+                self.height, self.offset, self.file_path, error
+            );
+        }
     }
 }
 
@@ -107,6 +131,7 @@ impl BlockFileReader {
         logger.log(format!("scanning {}...", file_name));
         let file_path = Path::new(&self.path).join(file_name).to_str().unwrap().to_string();
         let mut file = File::open(&file_path).unwrap();
+        let file_length = file.metadata().unwrap().len();
         let mut buffer = [0u8; 88];
         let mut offset = 0;
         let mut blocks = Vec::new();
@@ -115,16 +140,32 @@ impl BlockFileReader {
             if bytes == 0 {
                 break;
             }
-            assert!(bytes == 88);
-            let mut reader = HashingBufferReader::new(&buffer);
-            let magic = reader.read_u32_le(&mut None);
-            if magic == 0 {
+            // The node may stop while writing a block, the blocks that would follow have not been written yet.
+            let (magic, length, header) = match read_block_header(&buffer[..bytes]) {
+                Ok(Some(block_header)) => block_header,
+                Ok(None) => break,
+                Err(error) => {
+                    logger.log(format!("skipping the end of {} from offset {}: {}", file_name, offset, error));
+                    break;
+                }
+            };
+            assert!(
+                magic == BLOCK_MAGIC,
+                "Unexpected magic {:#x} at offset {} of {}",
+                magic,
+                offset,
+                file_name
+            );
+            offset += 8;
+            let end = offset + u64::try_from(length).unwrap();
+            if end > file_length {
+                logger.log(format!(
+                    "skipping the end of {} from offset {}: truncated block",
+                    file_name,
+                    offset - 8
+                ));
                 break;
             }
-            assert!(magic == 3652501241);
-            offset += 8;
-            let length: usize = reader.read_u32_le(&mut None).try_into().unwrap();
-            let header = BlockHeader::from_buffer(&buffer[8..]);
             blocks.push(FileBlock {
                 file_path: file_path.clone(),
                 offset,
@@ -132,8 +173,7 @@ impl BlockFileReader {
                 header,
                 height: 0,
             });
-            let length: u64 = length.try_into().unwrap();
-            offset += length;
+            offset = end;
             file.seek(SeekFrom::Start(offset)).unwrap();
         }
         blocks
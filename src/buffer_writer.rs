@@ -19,6 +19,39 @@ impl BufferWriter {
         self.write_buffer(&value.to_le_bytes());
     }
 
+    pub fn write_var_int_le(&mut self, value: u64) {
+        match value {
+            0..=0xFC => self.write_u8(value as u8),
+            0xFD..=0xFFFF => {
+                self.write_u8(0xFD);
+                self.write_buffer(&(value as u16).to_le_bytes());
+            }
+            0x10000..=0xFFFFFFFF => {
+                self.write_u8(0xFE);
+                self.write_u32(value as u32);
+            }
+            _ => {
+                self.write_u8(0xFF);
+                self.write_u64(value);
+            }
+        }
+    }
+
+    pub fn write_b128_var_int(&mut self, mut value: u64) {
+        let mut bytes = [0u8; 10];
+        let mut length = 0;
+        loop {
+            bytes[length] = (value & 0x7F) as u8 | if length > 0 { 0x80 } else { 0x00 };
+            if value <= 0x7F {
+                break;
+            }
+            value = (value >> 7) - 1;
+            length += 1;
+        }
+        bytes[..length + 1].reverse();
+        self.write_buffer(&bytes[..length + 1]);
+    }
+
     pub fn write_buffer(&mut self, value: &[u8]) {
         self.buffer.extend_from_slice(value);
     }
@@ -27,7 +60,52 @@ impl BufferWriter {
         self.write_buffer(&value);
     }
 
+    pub fn offset(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn write_u64_at(&mut self, offset: usize, value: u64) {
+        self.buffer[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
     pub fn buffer(self) -> Vec<u8> {
         self.buffer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::HashingBufferReader;
+
+    fn encode(value: u64) -> Vec<u8> {
+        let mut writer = super::BufferWriter::new();
+        writer.write_b128_var_int(value);
+        writer.buffer()
+    }
+
+    fn decode(buffer: &[u8]) -> u64 {
+        HashingBufferReader::new(buffer).read_b128_var_int(&mut None).unwrap()
+    }
+
+    #[test]
+    fn write_b128_var_int() {
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(1), vec![0x01]);
+        assert_eq!(encode(127), vec![0x7F]);
+        assert_eq!(encode(128), vec![0x80, 0x00]);
+        assert_eq!(encode(255), vec![0x80, 0x7F]);
+        assert_eq!(encode(256), vec![0x81, 0x00]);
+        assert_eq!(encode(16383), vec![0xFE, 0x7F]);
+        assert_eq!(encode(16384), vec![0xFF, 0x00]);
+        assert_eq!(encode(16511), vec![0xFF, 0x7F]);
+        assert_eq!(encode(65535), vec![0x82, 0xFE, 0x7F]);
+        assert_eq!(encode(1 << 32), vec![0x8E, 0xFE, 0xFE, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn read_b128_var_int() {
+        for value in [0, 1, 127, 128, 255, 256, 16383, 16384, 16511, 65535, 1 << 32, u64::MAX] {
+            assert_eq!(decode(&encode(value)), value);
+        }
+    }
+}
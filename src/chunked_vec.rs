@@ -0,0 +1,108 @@
+use crate::Arc;
+use std::ops::Index;
+
+const CHUNK_SIZE: usize = 1024;
+
+/// Vector whose full chunks are shared with its clones, so that cloning it or pushing to a clone copies at most one chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedVec<T> {
+    chunks: Vec<Arc<[T]>>,
+    tail: Vec<T>,
+}
+
+impl<T: Clone> ChunkedVec<T> {
+    pub fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            tail: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len() * CHUNK_SIZE + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.tail.len() == CHUNK_SIZE {
+            self.chunks.push(std::mem::take(&mut self.tail).into());
+        }
+        self.tail.push(value);
+    }
+
+    /// Last element, which is never part of a shared chunk as full chunks are only created by pushing the next element.
+    pub fn last(&self) -> Option<&T> {
+        self.tail.last()
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut T> {
+        self.tail.last_mut()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter()).chain(self.tail.iter())
+    }
+
+    /// Iterates over the elements from the last one, after skipping `offset` of them without going through them.
+    pub fn iter_rev(&self, offset: usize) -> impl Iterator<Item = &T> {
+        (0..self.len().saturating_sub(offset)).rev().map(|index| &self[index])
+    }
+}
+
+impl<T: Clone> Default for ChunkedVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<usize> for ChunkedVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.chunks.get(index / CHUNK_SIZE) {
+            Some(chunk) => &chunk[index % CHUNK_SIZE],
+            None => &self.tail[index - self.chunks.len() * CHUNK_SIZE],
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for ChunkedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iterator: I) -> Self {
+        let mut vec = Self::new();
+        for value in iterator {
+            vec.push(value);
+        }
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedVec, CHUNK_SIZE};
+
+    #[test]
+    fn push() {
+        let mut vec: ChunkedVec<usize> = (0..CHUNK_SIZE * 2 + 3).collect();
+        let clone = vec.clone();
+        vec.push(vec.len());
+        *vec.last_mut().unwrap() += 1;
+        assert_eq!(clone.len(), CHUNK_SIZE * 2 + 3);
+        assert_eq!(vec.len(), CHUNK_SIZE * 2 + 4);
+        assert_eq!(vec.last(), Some(&(CHUNK_SIZE * 2 + 4)));
+        assert_eq!(clone.last(), Some(&(CHUNK_SIZE * 2 + 2)));
+        assert!(vec.iter().take(CHUNK_SIZE * 2 + 3).eq(clone.iter()));
+        assert_eq!(vec[CHUNK_SIZE + 5], CHUNK_SIZE + 5);
+    }
+
+    #[test]
+    fn iter_rev() {
+        let vec: ChunkedVec<usize> = (0..CHUNK_SIZE + 10).collect();
+        assert!(vec.iter_rev(0).eq(vec.iter().rev()));
+        assert!(vec.iter_rev(15).eq((0..CHUNK_SIZE - 5).rev().collect::<Vec<_>>().iter()));
+        assert_eq!(vec.iter_rev(CHUNK_SIZE + 10).count(), 0);
+        assert_eq!(vec.iter_rev(usize::MAX).count(), 0);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{base64, hex, reverse_hex, Logger, Transaction};
+use crate::{base64, hex, reverse_hex, Logger};
 use hyper::body::HttpBody;
 use serde_json::{json, Value};
 use std::{str, error, fmt};
@@ -35,26 +35,28 @@ impl Client {
         }
     }
 
-    async fn request(&self, method: &str, params: Vec<Value>, logger: &Logger) -> Result<Value, Error> {
-        logger.log(format!("{} {}", method, json!(params).to_string()));
+    async fn post(&self, body: Value) -> (hyper::StatusCode, Vec<u8>) {
         let request = hyper::Request::builder()
             .method(hyper::Method::POST)
             .uri(&self.uri)
             .header("content-type", "application/json")
             .header("authorization", &self.authorization)
-            .body(hyper::Body::from(
-                json!({
-                    "jsonrpc": "1.0", "id": "indexer", "method": method, "params": params
-                })
-                .to_string(),
-            ))
+            .body(hyper::Body::from(body.to_string()))
             .unwrap();
         let mut response = self.client.request(request).await.unwrap();
         let mut body = Vec::new();
         while let Some(chunk) = response.body_mut().data().await {
             body.extend_from_slice(&chunk.unwrap());
         }
-        match response.status() {
+        (response.status(), body)
+    }
+
+    async fn request(&self, method: &str, params: Vec<Value>, logger: &Logger) -> Result<Value, Error> {
+        logger.log(format!("{} {}", method, json!(params).to_string()));
+        let (status, body) = self.post(json!({
+            "jsonrpc": "1.0", "id": "indexer", "method": method, "params": params
+        })).await;
+        match status {
             hyper::StatusCode::OK => {
                 let mut json: Value = serde_json::from_slice(&body).unwrap();
                 Ok(json["result"].take())
@@ -64,30 +66,33 @@ impl Client {
                 Err(serde_json::from_value(json["error"].take()).unwrap())
             }
             _ => {
-                panic!("{} {}", response.status(), str::from_utf8(&body).unwrap());
+                panic!("{} {}", status, str::from_utf8(&body).unwrap());
             }
         }
     }
 
-    pub async fn getrawmempool(&self, logger: &Logger) -> Vec<[u8; 32]> {
-        let result = self.request("getrawmempool", vec![json!(false)], logger).await.unwrap();
-        let result: Vec<String> = serde_json::from_value(result).unwrap();
-        result
+    /// Hashes of the mempool transactions with the mempool sequence, which the node increments each time a transaction
+    /// enters or leaves the mempool.
+    pub async fn getrawmempool(&self, logger: &Logger) -> (Vec<[u8; 32]>, u64) {
+        let mut result = self.request("getrawmempool", vec![json!(false), json!(true)], logger).await.unwrap();
+        let hashes: Vec<String> = serde_json::from_value(result["txids"].take()).unwrap();
+        let sequence: u64 = serde_json::from_value(result["mempool_sequence"].take()).unwrap();
+        let hashes = hashes
             .iter()
             .map(|hash| {
                 let mut result = [0u8; 32];
                 reverse_hex::decode_into(hash, &mut result).unwrap();
                 result
             })
-            .collect()
+            .collect();
+        (hashes, sequence)
     }
 
-    pub async fn getrawtransaction(&self, hash: &[u8; 32], logger: &Logger) -> Option<Transaction> {
+    pub async fn getrawtransaction(&self, hash: &[u8; 32], logger: &Logger) -> Option<Vec<u8>> {
         match self.request("getrawtransaction", vec![json!(reverse_hex::encode(hash))], logger).await {
             Ok(result) => {
                 let result: String = serde_json::from_value(result).unwrap();
-                let buffer = hex::decode(&result).unwrap();
-                Some(Transaction::from_slice(&buffer))
+                Some(hex::decode(&result).unwrap())
             },
             Err(error) => {
                 if error.code == -5 {
@@ -115,6 +120,49 @@ impl Client {
         }
     }
 
+    /// Times of the blocks, requested in a single batch.
+    pub async fn getblockheader_times(&self, hashes: &[[u8; 32]], logger: &Logger) -> Vec<u32> {
+        logger.log(format!("getblockheader {} blocks", hashes.len()));
+        let requests: Vec<Value> = hashes.iter().enumerate().map(|(id, hash)| {
+            json!({"jsonrpc": "1.0", "id": id, "method": "getblockheader", "params": [reverse_hex::encode(hash), true]})
+        }).collect();
+        let (status, body) = self.post(json!(requests)).await;
+        if status != hyper::StatusCode::OK {
+            panic!("{} {}", status, str::from_utf8(&body).unwrap());
+        }
+        let responses: Vec<Value> = serde_json::from_slice(&body).unwrap();
+        let mut times = vec![0; hashes.len()];
+        for mut response in responses {
+            if !response["error"].is_null() {
+                let error: Error = serde_json::from_value(response["error"].take()).unwrap();
+                panic!("getblockheader {}", error);
+            }
+            let id: usize = serde_json::from_value(response["id"].take()).unwrap();
+            times[id] = serde_json::from_value(response["result"]["time"].take()).unwrap();
+        }
+        times
+    }
+
+    pub async fn gettxoutsetinfo_muhash(&self, hash: &[u8; 32], logger: &Logger) -> Option<[u8; 32]> {
+        match self.request("gettxoutsetinfo", vec![json!("muhash"), json!(reverse_hex::encode(hash))], logger).await {
+            Ok(mut result) => match serde_json::from_value::<String>(result["muhash"].take()) {
+                Ok(muhash) => {
+                    let mut result = [0u8; 32];
+                    reverse_hex::decode_into(muhash, &mut result).ok()?;
+                    Some(result)
+                }
+                Err(_) => {
+                    logger.log("Could not get utxo set muhash: missing muhash in gettxoutsetinfo result");
+                    None
+                }
+            },
+            Err(error) => {
+                logger.log(format!("Could not get utxo set muhash: {}", error));
+                None
+            }
+        }
+    }
+
     pub async fn getblock(&self, hash: impl AsRef<[u8]>, logger: &Logger) -> Option<Vec<u8>> {
         let result = self
             .request("getblock", vec![json!(reverse_hex::encode(hash)), json!(0)], logger)
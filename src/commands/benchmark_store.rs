@@ -0,0 +1,57 @@
+use super::{argument, threads};
+use crate::{Chronometer, IndexOptions, IndexedStore, Logger};
+
+fn per_utxo(size: usize, utxos: usize) -> f64 {
+    (size as f64) / (utxos.max(1) as f64)
+}
+
+pub fn benchmark_store(arguments: &[String]) {
+    let path = argument(arguments, 0, "store file path");
+    let logger = Logger::new();
+    let buffer = std::fs::read(path).unwrap();
+
+    let chronometer = Chronometer::new();
+    let store = match IndexedStore::from_buffer(&buffer, threads(), &IndexOptions::none(), &logger) {
+        Some(store) => store,
+        None => panic!("Could not read store file {}", path),
+    };
+    let file_load_time = chronometer.elapsed();
+
+    let mut utxos = 0;
+    store.backend().iterate_unspent_transaction_outputs(|_, _| {
+        utxos += 1;
+    });
+
+    let chronometer = Chronometer::new();
+    let compact_buffer = store.to_buffer(&logger);
+    let compact_write_time = chronometer.elapsed();
+    drop(store);
+
+    let chronometer = Chronometer::new();
+    let store = IndexedStore::from_buffer(&compact_buffer, threads(), &IndexOptions::none(), &logger).unwrap();
+    let compact_load_time = chronometer.elapsed();
+    drop(store);
+
+    let chronometer = Chronometer::new();
+    let store = IndexedStore::from_buffer(&compact_buffer, 1, &IndexOptions::none(), &logger).unwrap();
+    let single_thread_load_time = chronometer.elapsed();
+    drop(store);
+
+    println!("utxos: {}", utxos);
+    println!(
+        "{}: {} bytes ({:.2} bytes/utxo), loaded in {}",
+        path,
+        buffer.len(),
+        per_utxo(buffer.len(), utxos),
+        file_load_time
+    );
+    println!(
+        "compact encoding: {} bytes ({:.2} bytes/utxo), written in {}, loaded in {} on {} threads and in {} on 1 thread",
+        compact_buffer.len(),
+        per_utxo(compact_buffer.len(), utxos),
+        compact_write_time,
+        compact_load_time,
+        threads(),
+        single_thread_load_time
+    );
+}
@@ -0,0 +1,98 @@
+use super::{argument, threads};
+use crate::{
+    configuration, json, reverse_hex, store::IntermediaryTransactionStore, BlockFileReader, BlockTrait, IndexedStore, Logger,
+    PartialLogger, TransactionOutputHashMap,
+};
+
+fn report(discrepancies: &mut usize, check: &str, message: String) {
+    *discrepancies += 1;
+    println!("{}", json!({"check": check, "message": message}));
+}
+
+fn check_headers(store: &IndexedStore, mut report: impl FnMut(&str, String)) {
+    for (height, pair) in store.block_headers().windows(2).enumerate() {
+        if pair[1].previous_block_hash != pair[0].hash {
+            report(
+                "headers",
+                format!("block {} does not link to block {}", reverse_hex::encode(pair[1].hash), height),
+            );
+        }
+    }
+}
+
+/// Replays the blocks from `from` to the tip of the store and compares the outputs created up to `to` that are still
+/// unspent with the UTXO set of the store.
+fn check_blocks(
+    store: &IndexedStore,
+    block_files_path: &str,
+    from: usize,
+    to: usize,
+    logger: &Logger,
+    mut report: impl FnMut(&str, String),
+) {
+    let height = store.height() - 1;
+    assert!(from <= to && to <= height, "Expecting a height range within 0 - {}", height);
+    let blocks = BlockFileReader::new(block_files_path).blocks(1, logger);
+    assert!(blocks.len() > height, "Block files end at height {}", blocks.len() as isize - 1);
+    let mut transaction_store = IntermediaryTransactionStore::new();
+    let mut partial_logger = PartialLogger::new(1000, logger);
+    for block in &blocks[from..=height] {
+        partial_logger.log(|_| format!("replaying block {}...", block.height()));
+        if block.hash() != store.block_headers()[block.height()].hash {
+            report("blocks", format!("block {} differs from block files", block.height()));
+        }
+        transaction_store.add_block(block);
+    }
+    let expected = transaction_store.backend().unspent_txos();
+    let mut found = TransactionOutputHashMap::new();
+    store.backend().iterate_unspent_transaction_outputs(|txo, coin| {
+        if (from..=to).contains(&(coin.height as usize)) {
+            found.insert(txo.clone(), ());
+            let outpoint = format!("{}:{}", reverse_hex::encode(txo.hash), txo.index);
+            match expected.get(txo) {
+                Some(expected) if expected == coin => {}
+                Some(expected) => report("blocks", format!("utxo {} is {:?} instead of {:?}", outpoint, coin, expected)),
+                None => report("blocks", format!("utxo {} is spent or does not exist", outpoint)),
+            }
+        }
+    });
+    for (txo, coin) in expected {
+        if coin.height as usize <= to && found.get(txo).is_none() {
+            report("blocks", format!("utxo {}:{} is missing", reverse_hex::encode(txo.hash), txo.index));
+        }
+    }
+}
+
+pub fn check(arguments: &[String]) {
+    let store_file_path = argument(arguments, 0, "store file path");
+    let logger = Logger::new();
+    let store = match IndexedStore::from_file(store_file_path, threads(), &configuration::index_options(), &logger) {
+        Some(store) => store,
+        None => panic!("Could not read store file {}", store_file_path),
+    };
+    let mut discrepancies = 0;
+    logger.log("checking block headers...");
+    check_headers(&store, |check, message| report(&mut discrepancies, check, message));
+    logger.log("checking indexes...");
+    store.backend().check(|check, message| report(&mut discrepancies, check, message));
+    if let Some(block_files_path) = arguments.get(1) {
+        let from = argument(arguments, 2, "from height").parse().unwrap();
+        let to = argument(arguments, 3, "to height").parse().unwrap();
+        logger.log("checking blocks...");
+        check_blocks(&store, block_files_path, from, to, &logger, |check, message| {
+            report(&mut discrepancies, check, message)
+        });
+    }
+    logger.log("checking store done!");
+    println!(
+        "{}",
+        json!({
+            "height": store.height() - 1,
+            "bestblock": reverse_hex::encode(store.last_block_hash().unwrap()),
+            "discrepancies": discrepancies,
+        })
+    );
+    if discrepancies > 0 {
+        std::process::exit(1);
+    }
+}
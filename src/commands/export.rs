@@ -0,0 +1,107 @@
+use super::{argument, threads};
+use crate::{
+    hex, json, reverse_hex,
+    snapshot::{SnapshotCoin, SnapshotWriter},
+    transaction::Output,
+    IndexOptions, IndexedStore, Logger, PartialLogger,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+fn export_csv(store: &IndexedStore, writer: &mut impl Write, logger: &Logger) {
+    let mut logger = PartialLogger::new(1000000, logger);
+    writeln!(writer, "txid,vout,value,address,script,height").unwrap();
+    store.backend().iterate_unspent_transaction_outputs(|txo, coin| {
+        logger.log(|index| format!("exporting utxo {}...", index));
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            reverse_hex::encode(txo.hash),
+            txo.index,
+            coin.value,
+            coin.address().map(|address| address.to_string()).unwrap_or_default(),
+            hex::encode(coin.script.to_script()),
+            coin.height,
+        )
+        .unwrap();
+    });
+}
+
+fn export_ndjson(store: &IndexedStore, writer: &mut impl Write, logger: &Logger) {
+    let mut logger = PartialLogger::new(1000000, logger);
+    store.backend().iterate_unspent_transaction_outputs(|txo, coin| {
+        logger.log(|index| format!("exporting utxo {}...", index));
+        let line = json!({
+            "txid": reverse_hex::encode(txo.hash),
+            "vout": txo.index,
+            "value": coin.value,
+            "address": coin.address().map(|address| address.to_string()),
+            "script": hex::encode(coin.script.to_script()),
+            "height": coin.height,
+        });
+        writeln!(writer, "{}", line).unwrap();
+    });
+}
+
+fn export_dumptxoutset(store: &IndexedStore, writer: &mut BufWriter<File>, logger: &Logger) {
+    let mut snapshot = SnapshotWriter::new(writer, store.last_block_hash().unwrap_or_default());
+    let mut partial_logger = PartialLogger::new(1000000, logger);
+    let mut coins = Vec::new();
+    let mut hash = [0u8; 32];
+    let mut skipped = 0;
+    store.backend().iterate_unspent_transaction_outputs(|txo, coin| {
+        partial_logger.log(|index| format!("exporting utxo {}...", index));
+        if coin.is_in_utxo_set() {
+            if txo.hash != hash && !coins.is_empty() {
+                snapshot.write_coins(hash, &coins);
+                coins.clear();
+            }
+            hash = txo.hash;
+            let output = Output {
+                value: coin.value,
+                script: coin.script.to_script(),
+            };
+            coins.push((
+                txo.index,
+                SnapshotCoin {
+                    height: coin.height,
+                    coinbase: coin.coinbase,
+                    output,
+                },
+            ));
+        } else {
+            skipped += 1;
+        }
+    });
+    if !coins.is_empty() {
+        snapshot.write_coins(hash, &coins);
+    }
+    let exported = snapshot.finish();
+    logger.log(format!(
+        "exported {} utxos, skipped {} utxos that are not part of Bitcoin Core's UTXO set",
+        exported, skipped
+    ));
+}
+
+pub fn export(arguments: &[String]) {
+    let format = argument(arguments, 0, "format");
+    let store_file_path = argument(arguments, 1, "store file path");
+    let output_file_path = argument(arguments, 2, "output file path");
+    let logger = Logger::new();
+    let store = match IndexedStore::from_file(store_file_path, threads(), &IndexOptions::none(), &logger) {
+        Some(store) => store,
+        None => panic!("Could not read store file {}", store_file_path),
+    };
+    let mut writer = BufWriter::new(File::create(output_file_path).unwrap());
+    logger.log("exporting utxos...");
+    match format {
+        "csv" => export_csv(&store, &mut writer, &logger),
+        "ndjson" => export_ndjson(&store, &mut writer, &logger),
+        "dumptxoutset" => export_dumptxoutset(&store, &mut writer, &logger),
+        _ => panic!("Unknown export format {}", format),
+    }
+    writer.flush().unwrap();
+    logger.log("exporting utxos done!");
+}
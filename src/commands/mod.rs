@@ -0,0 +1,21 @@
+mod benchmark_store;
+mod check;
+mod export;
+mod utxo_set_hash;
+
+pub use self::benchmark_store::benchmark_store;
+pub use self::check::check;
+pub use self::export::export;
+pub use self::utxo_set_hash::utxo_set_hash;
+
+fn argument<'a>(arguments: &'a [String], index: usize, name: &str) -> &'a str {
+    match arguments.get(index) {
+        Some(argument) => argument,
+        None => panic!("Missing {} argument", name),
+    }
+}
+
+/// Number of threads used to load store files, one per available core.
+fn threads() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
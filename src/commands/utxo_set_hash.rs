@@ -0,0 +1,21 @@
+use super::{argument, threads};
+use crate::{json, reverse_hex, IndexOptions, IndexedStore, Logger};
+
+pub fn utxo_set_hash(arguments: &[String]) {
+    let path = argument(arguments, 0, "store file path");
+    let logger = Logger::new();
+    let store = match IndexedStore::from_file(path, threads(), &IndexOptions::none(), &logger) {
+        Some(store) => store,
+        None => panic!("Could not read store file {}", path),
+    };
+    logger.log("computing hash_serialized_3...");
+    let hash_serialized = store.backend().hash_serialized();
+    logger.log("computing hash_serialized_3 done!");
+    let result = json!({
+        "height": store.height() - 1,
+        "bestblock": reverse_hex::encode(store.last_block_hash().unwrap()),
+        "hash_serialized_3": reverse_hex::encode(hash_serialized),
+        "muhash": reverse_hex::encode(store.backend().muhash().unwrap().finalize()),
+    });
+    println!("{}", serde_json::to_string_pretty(&result).unwrap());
+}
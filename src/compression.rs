@@ -0,0 +1,214 @@
+use crate::secp256k1::decompress_public_key;
+
+pub const SPECIAL_SCRIPTS: u64 = 6;
+pub const MAX_SCRIPT_SIZE: u64 = 10000;
+
+pub fn compress_amount(value: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    let mut exponent = 0;
+    let mut value = value;
+    while value.is_multiple_of(10) && exponent < 9 {
+        value /= 10;
+        exponent += 1;
+    }
+    if exponent < 9 {
+        let digit = value % 10;
+        value /= 10;
+        1 + (value * 9 + digit - 1) * 10 + exponent
+    } else {
+        1 + (value - 1) * 10 + 9
+    }
+}
+
+pub fn decompress_amount(value: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    let value = value - 1;
+    let exponent = value % 10;
+    let value = value / 10;
+    let mut amount = if exponent < 9 {
+        let digit = (value % 9) + 1;
+        (value / 9) * 10 + digit
+    } else {
+        value + 1
+    };
+    for _ in 0..exponent {
+        amount *= 10;
+    }
+    amount
+}
+
+pub fn special_script_size(kind: u64) -> usize {
+    match kind {
+        0 | 1 => 20,
+        2..=5 => 32,
+        _ => panic!("Invalid special script kind {}", kind),
+    }
+}
+
+pub fn compress_script(script: &[u8]) -> Option<Vec<u8>> {
+    if script.len() == 25 && script[0] == 0x76 && script[1] == 0xa9 && script[2] == 0x14 && script[23] == 0x88 && script[24] == 0xac {
+        let mut compressed = vec![0x00];
+        compressed.extend_from_slice(&script[3..23]);
+        return Some(compressed);
+    }
+    if script.len() == 23 && script[0] == 0xa9 && script[1] == 0x14 && script[22] == 0x87 {
+        let mut compressed = vec![0x01];
+        compressed.extend_from_slice(&script[2..22]);
+        return Some(compressed);
+    }
+    if script.len() == 35 && script[0] == 0x21 && (script[1] == 0x02 || script[1] == 0x03) && script[34] == 0xac {
+        return Some(script[1..34].to_vec());
+    }
+    if script.len() == 67 && script[0] == 0x41 && script[1] == 0x04 && script[66] == 0xac {
+        let mut compressed = vec![0x04 | (script[65] & 0x01)];
+        compressed.extend_from_slice(&script[2..34]);
+        let mut public_key = [0u8; 33];
+        public_key[0] = 0x02 | (script[65] & 0x01);
+        public_key[1..].copy_from_slice(&script[2..34]);
+        if decompress_public_key(&public_key)?[..] == script[1..66] {
+            return Some(compressed);
+        }
+    }
+    None
+}
+
+pub fn decompress_script(kind: u64, payload: &[u8]) -> Vec<u8> {
+    match kind {
+        0 => {
+            let mut script = vec![0x76, 0xa9, 0x14];
+            script.extend_from_slice(payload);
+            script.extend_from_slice(&[0x88, 0xac]);
+            script
+        }
+        1 => {
+            let mut script = vec![0xa9, 0x14];
+            script.extend_from_slice(payload);
+            script.push(0x87);
+            script
+        }
+        2 | 3 => {
+            let mut script = vec![0x21, kind as u8];
+            script.extend_from_slice(payload);
+            script.push(0xac);
+            script
+        }
+        4 | 5 => {
+            let mut public_key = [0u8; 33];
+            public_key[0] = (kind - 2) as u8;
+            public_key[1..].copy_from_slice(payload);
+            // Like Bitcoin Core, a public key that is not on the curve decompresses to an empty script.
+            match decompress_public_key(&public_key) {
+                Some(public_key) => {
+                    let mut script = vec![0x41];
+                    script.extend_from_slice(&public_key);
+                    script.push(0xac);
+                    script
+                }
+                None => Vec::new(),
+            }
+        }
+        _ => panic!("Invalid special script kind {}", kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hex;
+
+    const CENT: u64 = 1_000_000;
+    const COIN: u64 = 100_000_000;
+
+    #[test]
+    fn compress_amount() {
+        assert_eq!(super::compress_amount(0), 0);
+        assert_eq!(super::compress_amount(1), 1);
+        assert_eq!(super::compress_amount(CENT), 7);
+        assert_eq!(super::compress_amount(COIN), 9);
+        assert_eq!(super::compress_amount(50 * COIN), 50);
+        assert_eq!(super::compress_amount(21_000_000 * COIN), 21_000_000);
+    }
+
+    #[test]
+    fn decompress_amount() {
+        assert_eq!(super::decompress_amount(0), 0);
+        assert_eq!(super::decompress_amount(1), 1);
+        assert_eq!(super::decompress_amount(7), CENT);
+        assert_eq!(super::decompress_amount(9), COIN);
+        assert_eq!(super::decompress_amount(50), 50 * COIN);
+        assert_eq!(super::decompress_amount(21_000_000), 21_000_000 * COIN);
+        for value in (0..100_000).chain((0..100_000).map(|value| value * CENT)) {
+            assert_eq!(super::decompress_amount(super::compress_amount(value)), value);
+        }
+    }
+
+    fn compress(script: &str) -> Option<String> {
+        super::compress_script(&hex::decode(script).unwrap()).map(hex::encode)
+    }
+
+    #[test]
+    fn compress_script() {
+        assert_eq!(
+            compress("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"),
+            Some(String::from("0062e907b15cbf27d5425399ebf6f0fb50ebb88f18"))
+        );
+        assert_eq!(
+            compress("a914e9c3dd0c07aac76179ebc76a6c78d4d67c6c160a87"),
+            Some(String::from("01e9c3dd0c07aac76179ebc76a6c78d4d67c6c160a"))
+        );
+        assert_eq!(
+            compress("2103c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5ac"),
+            Some(String::from("03c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"))
+        );
+        assert_eq!(
+            compress(
+                "4104\
+                 79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+                 483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8ac"
+            ),
+            Some(String::from("0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"))
+        );
+        assert_eq!(
+            compress(
+                "4104\
+                 79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+                 483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b9ac"
+            ),
+            None
+        );
+        assert_eq!(compress("0014751e76e8199196d454941c45d1b3a323f1433bd6"), None);
+    }
+
+    fn decompress(kind: u64, payload: &str) -> String {
+        hex::encode(super::decompress_script(kind, &hex::decode(payload).unwrap()))
+    }
+
+    #[test]
+    fn decompress_script() {
+        assert_eq!(
+            decompress(0, "62e907b15cbf27d5425399ebf6f0fb50ebb88f18"),
+            "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"
+        );
+        assert_eq!(
+            decompress(1, "e9c3dd0c07aac76179ebc76a6c78d4d67c6c160a"),
+            "a914e9c3dd0c07aac76179ebc76a6c78d4d67c6c160a87"
+        );
+        assert_eq!(
+            decompress(3, "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"),
+            "2103c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5ac"
+        );
+        assert_eq!(
+            decompress(4, "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+            "4104\
+             79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+             483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8ac"
+        );
+        assert_eq!(
+            decompress(4, "0000000000000000000000000000000000000000000000000000000000000005"),
+            ""
+        );
+    }
+}
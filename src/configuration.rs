@@ -1,3 +1,4 @@
+use crate::IndexOptions;
 use std::{env, error, net::IpAddr};
 
 #[derive(Debug, Clone)]
@@ -8,12 +9,14 @@ pub struct Configuration {
     batch_size: usize,
     block_files_path: String,
     store_file_path: String,
+    snapshot_file_path: Option<String>,
     confirmations: usize,
     update_interval: u64,
     rpc_server_host: String,
     rpc_server_port: u16,
     rpc_server_user: String,
     rpc_server_password: String,
+    index_options: IndexOptions,
 }
 
 fn var(key: &str, default: Option<String>) -> Result<String, String> {
@@ -30,6 +33,20 @@ fn var_map<T, E: error::Error>(key: &str, mut f: impl FnMut(&str) -> Result<T, E
     }
 }
 
+/// Optional indexes enabled by the `HISTORY_INDEX`, `SPEND_INDEX`, `STATS_INDEX` and `OP_RETURN_INDEX` environment variables.
+pub fn index_options() -> IndexOptions {
+    let history = var_map("HISTORY_INDEX", |history| history.parse(), Some(false)).unwrap();
+    let spends = var_map("SPEND_INDEX", |spends| spends.parse(), Some(false)).unwrap();
+    let stats = var_map("STATS_INDEX", |stats| stats.parse(), Some(false)).unwrap();
+    let op_returns = var_map("OP_RETURN_INDEX", |op_returns| op_returns.parse(), Some(false)).unwrap();
+    IndexOptions {
+        history,
+        spends,
+        stats,
+        op_returns,
+    }
+}
+
 impl Configuration {
     pub fn new() -> Self {
         let host = var_map("HOST", |host| host.parse(), Some("127.0.0.1".parse().unwrap())).unwrap();
@@ -38,12 +55,18 @@ impl Configuration {
         let batch_size = var_map("BATCH_SIZE", |threads| threads.parse(), None).unwrap();
         let store_file_path = var("STORE_FILE_PATH", None).unwrap();
         let block_files_path = var("BLOCK_FILES_PATH", None).unwrap();
+        let snapshot_file_path = var("SNAPSHOT_FILE_PATH", None).ok();
         let update_interval = var_map("UPDATE_INTERVAL", |interval| interval.parse(), Some(1000)).unwrap();
         let confirmations = var_map("CONFIRMATIONS", |confirmations| confirmations.parse(), Some(6)).unwrap();
         let rpc_server_host = var("RPC_SERVER_HOST", None).unwrap();
         let rpc_server_port = var_map("RPC_SERVER_PORT", |port| port.parse(), None).unwrap();
         let rpc_server_user = var("RPC_SERVER_USER", None).unwrap();
         let rpc_server_password = var("RPC_SERVER_PASSWORD", None).unwrap();
+        let index_options = index_options();
+        assert!(
+            snapshot_file_path.is_none() || !index_options.any(),
+            "SNAPSHOT_FILE_PATH cannot be used with the optional indexes, which need every block since genesis"
+        );
         Self {
             host,
             port,
@@ -51,12 +74,14 @@ impl Configuration {
             batch_size,
             store_file_path,
             block_files_path,
+            snapshot_file_path,
             update_interval,
             confirmations,
             rpc_server_host,
             rpc_server_port,
             rpc_server_user,
             rpc_server_password,
+            index_options,
         }
     }
 
@@ -84,6 +109,10 @@ impl Configuration {
         &self.store_file_path
     }
 
+    pub fn snapshot_file_path(&self) -> Option<&str> {
+        self.snapshot_file_path.as_deref()
+    }
+
     pub fn confirmations(&self) -> usize {
         self.confirmations
     }
@@ -107,4 +136,8 @@ impl Configuration {
     pub fn rpc_server_password(&self) -> &str {
         &self.rpc_server_password
     }
+
+    pub fn index_options(&self) -> &IndexOptions {
+        &self.index_options
+    }
 }
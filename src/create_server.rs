@@ -1,4 +1,12 @@
-use crate::{json, reverse_hex, Address, Arc, HashMap, Server, State, TransactionOutput};
+use crate::{
+    balance_stats::RICH_LIST_SIZE,
+    fees::{BlockFeeStats, FEERATE_TARGETS},
+    hex, json, reverse_hex,
+    script::{self, ScriptType},
+    state::BlockSelector,
+    store::{AddressStats, Coin, HistoryEntry, OpReturn, MEMPOOL_HEIGHT, SCRIPT_TYPES},
+    Address, Arc, HashMap, Logger, Server, State, TransactionOutput,
+};
 use hyper::{Body, Response, StatusCode};
 use std::{error, fmt};
 
@@ -62,6 +70,51 @@ fn parse_addresses_from_body(body: &Vec<u8>) -> Result<Vec<Address>, Error> {
         .collect()
 }
 
+fn parse_txid(txid: &str) -> Result<[u8; 32], Error> {
+    reverse_hex::decode(txid)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| Error::new(format!(r#"Invalid txid "{}""#, txid)))
+}
+
+fn parse_transaction_output_from_parameters(parameters: &HashMap<String, String>) -> Result<TransactionOutput, Error> {
+    let hash = parse_txid(parameters.get("txid").unwrap())?;
+    let vout = parameters.get("vout").unwrap();
+    let index = vout.parse().map_err(|_| Error::new(format!(r#"Invalid vout "{}""#, vout)))?;
+    Ok(TransactionOutput::new(hash, index))
+}
+
+fn parse_transaction_outputs_from_body(body: &[u8]) -> Result<Vec<TransactionOutput>, Error> {
+    let body = std::str::from_utf8(body).map_err(|_| Error::new("Invalid utf8 body"))?;
+    let json: serde_json::Value = serde_json::from_str(body).map_err(|_| Error::new("Invalid JSON body"))?;
+    let array = json.as_array().ok_or_else(|| Error::new("Expecting array in JSON body"))?;
+    array
+        .iter()
+        .map(|output| {
+            let txid = output["txid"].as_str();
+            let vout = output["vout"].as_u64().and_then(|vout| vout.try_into().ok());
+            match (txid, vout) {
+                (Some(txid), Some(vout)) => Ok(TransactionOutput::new(parse_txid(txid)?, vout)),
+                _ => Err(Error::new(r#"Expecting array of {"txid", "vout"} objects in JSON body"#)),
+            }
+        })
+        .collect()
+}
+
+fn parse_op_return_prefix(prefix: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(prefix)
+        .ok()
+        .filter(|prefix| !prefix.is_empty())
+        .ok_or_else(|| Error::new(format!(r#"Invalid prefix "{}""#, prefix)))
+}
+
+fn parse_payload_hash(hash: &str) -> Result<[u8; 32], Error> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| Error::new(format!(r#"Invalid hash "{}""#, hash)))
+}
+
 fn parse_confirmations(parameters: &HashMap<String, String>, max_confirmations: usize) -> Result<usize, Error> {
     match parameters.get("confirmations") {
         Some(confirmations) => match confirmations.parse::<usize>() {
@@ -81,56 +134,544 @@ fn parse_confirmations(parameters: &HashMap<String, String>, max_confirmations:
     }
 }
 
+fn parse_exclude_immature_coinbase(parameters: &HashMap<String, String>) -> Result<bool, Error> {
+    match parameters.get("exclude_immature_coinbase").map(String::as_str) {
+        Some("true") => Ok(true),
+        Some("false") | None => Ok(false),
+        Some(value) => Err(Error::new(format!(r#"Invalid exclude_immature_coinbase parameter "{}""#, value))),
+    }
+}
+
+fn parse_include_replaced(parameters: &HashMap<String, String>) -> Result<bool, Error> {
+    match parameters.get("include_replaced").map(String::as_str) {
+        Some("true") => Ok(true),
+        Some("false") | None => Ok(false),
+        Some(value) => Err(Error::new(format!(r#"Invalid include_replaced parameter "{}""#, value))),
+    }
+}
+
+fn parse_usize(parameters: &HashMap<String, String>, name: &str, default: usize, max: usize) -> Result<usize, Error> {
+    match parameters.get(name) {
+        Some(value) => match value.parse::<usize>() {
+            Ok(value) if value <= max => Ok(value),
+            Ok(_) => Err(Error::new(format!("Expecting {} parameter to be less or equal to {}", name, max))),
+            Err(_) => Err(Error::new(format!(r#"Invalid {} parameter "{}""#, name, value))),
+        },
+        None => Ok(default),
+    }
+}
+
+fn parse_block_selector(parameters: &HashMap<String, String>) -> Result<Option<BlockSelector>, Error> {
+    match (parameters.get("height"), parameters.get("time")) {
+        (Some(_), Some(_)) => Err(Error::new("Expecting either a height or a time parameter")),
+        (Some(height), None) => match height.parse() {
+            Ok(height) => Ok(Some(BlockSelector::Height(height))),
+            Err(_) => Err(Error::new(format!(r#"Invalid height parameter "{}""#, height))),
+        },
+        (None, Some(time)) => match time.parse() {
+            Ok(time) => Ok(Some(BlockSelector::Time(time))),
+            Err(_) => Err(Error::new(format!(r#"Invalid time parameter "{}""#, time))),
+        },
+        (None, None) => Ok(None),
+    }
+}
+
 fn format_value(balance: u64) -> f64 {
     (balance as f64) / 1e8
 }
 
-fn format_utxo(utxo: &TransactionOutput, value: u64) -> serde_json::Value {
+fn format_utxo(utxo: &TransactionOutput, coin: &Coin, confirmations: u32, replaceable: Option<bool>) -> serde_json::Value {
+    let mut value = json!({
+        "hash": reverse_hex::encode(utxo.hash),
+        "vout": utxo.index,
+        "value": format_value(coin.value),
+        "height": format_height(coin.height),
+        "confirmations": confirmations,
+        "coinbase": coin.coinbase
+    });
+    if let Some(replaceable) = replaceable {
+        value["replaceable"] = json!(replaceable);
+        value["replaced_by"] = json!(null);
+    }
+    value
+}
+
+fn format_replaced_utxo(utxo: &TransactionOutput, value: u64, replaced_by: &[u8; 32]) -> serde_json::Value {
     json!({
         "hash": reverse_hex::encode(utxo.hash),
         "vout": utxo.index,
-        "value": format_value(value)
+        "value": format_value(value),
+        "height": null,
+        "confirmations": 0,
+        "replaced_by": reverse_hex::encode(replaced_by)
     })
 }
 
+fn format_height(height: u32) -> Option<u32> {
+    if height == MEMPOOL_HEIGHT {
+        None
+    } else {
+        Some(height)
+    }
+}
+
+fn format_history_entry(entry: &HistoryEntry, confirmations: u32) -> serde_json::Value {
+    let (sent, amount) = entry.net_value();
+    json!({
+        "hash": reverse_hex::encode(entry.hash),
+        "height": format_height(entry.height),
+        "confirmations": confirmations,
+        "direction": if sent { "sent" } else { "received" },
+        "amount": format_value(amount),
+        "received": format_value(entry.received_value()),
+        "sent": format_value(entry.sent_value())
+    })
+}
+
+fn format_script(script: &[u8]) -> serde_json::Value {
+    let script_type = script::classify(script);
+    let mut value = json!({
+        "hex": hex::encode(script),
+        "asm": script::to_asm(script),
+        "type": script_type.name()
+    });
+    if let ScriptType::Multisig(required, keys) = script_type {
+        value["required"] = json!(required);
+        value["keys"] = json!(keys);
+    }
+    value
+}
+
+fn format_op_return(entry: &OpReturn, confirmations: u32) -> serde_json::Value {
+    json!({
+        "hash": reverse_hex::encode(entry.txo.hash),
+        "vout": entry.txo.index,
+        "height": format_height(entry.height),
+        "confirmations": confirmations,
+        "payload": hex::encode(&entry.payload)
+    })
+}
+
+fn format_output(txo: &TransactionOutput, coin: &Option<(Coin, u32)>) -> serde_json::Value {
+    match coin {
+        Some((coin, confirmations)) => json!({
+            "hash": reverse_hex::encode(txo.hash),
+            "vout": txo.index,
+            "unspent": true,
+            "value": format_value(coin.value),
+            "address": coin.address().map(|address| address.to_string()),
+            "height": format_height(coin.height),
+            "confirmations": confirmations,
+            "coinbase": coin.coinbase,
+            "script": format_script(&coin.script.to_script())
+        }),
+        None => json!({
+            "hash": reverse_hex::encode(txo.hash),
+            "vout": txo.index,
+            "unspent": false
+        }),
+    }
+}
+
+async fn get_historical_balance(state: Arc<State>, address: &Address, selector: BlockSelector) -> Response<Body> {
+    match state.historical_transaction_outputs(address, &selector).await {
+        Some(Some((height, block_header, txos))) => respond_ok(json!({
+            "height": height,
+            "hash": reverse_hex::encode(block_header.hash),
+            "time": block_header.time,
+            "balance": format_value(txos.iter().map(|(_, value, _)| value).sum()),
+            "utxos": txos
+                .iter()
+                .map(|(txo, value, height)| json!({
+                    "hash": reverse_hex::encode(txo.hash),
+                    "vout": txo.index,
+                    "value": format_value(*value),
+                    "height": height
+                }))
+                .collect::<Vec<_>>()
+        })),
+        Some(None) => respond_error(Error::new("No block of the store matches the height or time parameter")),
+        None => respond_error(Error::new("History index is disabled")),
+    }
+}
+
 async fn get_balance(state: Arc<State>, address: &Address, parameters: &HashMap<String, String>) -> Response<Body> {
+    if let Some(selector) = unwrap!(parse_block_selector(parameters)) {
+        return get_historical_balance(state, address, selector).await;
+    }
     let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
-    respond_ok(json!(format_value(state.balance(&address, confirmations).await)))
+    let exclude_immature_coinbase = unwrap!(parse_exclude_immature_coinbase(parameters));
+    respond_ok(json!(format_value(
+        state.balance(address, confirmations, exclude_immature_coinbase).await
+    )))
 }
 
 async fn get_balance_array(state: Arc<State>, addresses: Vec<Address>, parameters: &HashMap<String, String>) -> Response<Body> {
     let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    let exclude_immature_coinbase = unwrap!(parse_exclude_immature_coinbase(parameters));
     respond_ok(json!(state
-        .balance_array(addresses, confirmations)
+        .balance_array(addresses, confirmations, exclude_immature_coinbase)
         .await
         .iter()
         .map(|(address, balance)| json!({"address": address.to_string(), "balance": (*balance as f64)/1e8}))
         .collect::<Vec<_>>()))
 }
 
+async fn get_replaced_utxos(state: &State, address: &Address, include_replaced: bool, confirmations: usize) -> Vec<serde_json::Value> {
+    if !include_replaced || confirmations > 0 {
+        return Vec::new();
+    }
+    let txos = state.replaced_transaction_outputs(address).await;
+    txos.iter()
+        .map(|(utxo, value, replaced_by)| format_replaced_utxo(utxo, *value, replaced_by))
+        .collect()
+}
+
 async fn get_utxos_array(state: Arc<State>, addresses: Vec<Address>, parameters: &HashMap<String, String>) -> Response<Body> {
     let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
-    respond_ok(json!(state
-        .transaction_outputs_array(addresses, confirmations)
-        .await
-        .iter()
-        .map(
-            |(address, utxos)| json!({"address": address.to_string(), "utxos": utxos.iter().map(|(utxo, value)| format_utxo(utxo, *value)).collect::<Vec<_>>()})
-        )
-        .collect::<Vec<_>>()))
+    let include_replaced = unwrap!(parse_include_replaced(parameters));
+    let mut result = Vec::new();
+    for (address, utxos) in state.transaction_outputs_array(addresses, confirmations).await {
+        let mut utxos: Vec<_> = utxos
+            .iter()
+            .map(|(utxo, coin, confirmations, replaceable)| format_utxo(utxo, coin, *confirmations, *replaceable))
+            .collect();
+        utxos.extend(get_replaced_utxos(&state, &address, include_replaced, confirmations).await);
+        result.push(json!({"address": address.to_string(), "utxos": utxos}));
+    }
+    respond_ok(json!(result))
 }
 
 async fn get_utxos(state: Arc<State>, address: &Address, parameters: &HashMap<String, String>) -> Response<Body> {
     let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    let include_replaced = unwrap!(parse_include_replaced(parameters));
     let mut utxos = Vec::new();
     state
-        .iterate_transaction_outputs(&address, confirmations, |utxo, value| {
-            utxos.push(format_utxo(utxo, value));
+        .iterate_transaction_outputs(address, confirmations, |utxo, coin, confirmations, replaceable| {
+            utxos.push(format_utxo(utxo, coin, confirmations, replaceable));
         })
         .await;
+    utxos.extend(get_replaced_utxos(&state, address, include_replaced, confirmations).await);
     respond_ok(json!(utxos))
 }
 
+async fn get_address_stats(state: Arc<State>, address: &Address, parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    match state.address_stats(address, confirmations).await {
+        Some(stats) => {
+            let stats = stats.unwrap_or_else(|| AddressStats::new(MEMPOOL_HEIGHT));
+            let balance = match stats.received.checked_sub(stats.sent) {
+                Some(balance) => balance,
+                None => return respond_internal_error(Error::new("Address stats have sent more than received")),
+            };
+            respond_ok(json!({
+                "received": format_value(stats.received),
+                "sent": format_value(stats.sent),
+                "balance": format_value(balance),
+                "funded_outputs": stats.funded_outputs,
+                "spent_outputs": stats.spent_outputs,
+                "first_seen": format_height(stats.first_seen),
+                "last_seen": format_height(stats.last_seen)
+            }))
+        }
+        None => respond_error(Error::new("Stats index is disabled")),
+    }
+}
+
+async fn get_history(state: Arc<State>, address: &Address, parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    let offset = unwrap!(parse_usize(parameters, "offset", 0, usize::MAX));
+    let limit = unwrap!(parse_usize(parameters, "limit", 100, 1000));
+    match state.history(address, confirmations, offset, limit).await {
+        Some((total, entries)) => respond_ok(json!({
+            "total": total,
+            "entries": entries
+                .iter()
+                .map(|(entry, confirmations)| format_history_entry(entry, *confirmations))
+                .collect::<Vec<_>>()
+        })),
+        None => respond_error(Error::new("History index is disabled")),
+    }
+}
+
+async fn get_output(state: Arc<State>, txo: TransactionOutput, parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    let outputs = state.unspent_transaction_outputs(vec![txo], confirmations).await;
+    let (txo, coin) = &outputs[0];
+    respond_ok(format_output(txo, coin))
+}
+
+async fn get_output_array(state: Arc<State>, txos: Vec<TransactionOutput>, parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    respond_ok(json!(state
+        .unspent_transaction_outputs(txos, confirmations)
+        .await
+        .iter()
+        .map(|(txo, coin)| format_output(txo, coin))
+        .collect::<Vec<_>>()))
+}
+
+async fn get_spender(state: Arc<State>, txo: &TransactionOutput, parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    match state.spender(txo, confirmations).await {
+        Some(spender) => respond_ok(json!(spender.map(|(spender, confirmations)| json!({
+            "hash": reverse_hex::encode(spender.hash),
+            "vin": spender.index,
+            "height": format_height(spender.height),
+            "confirmations": confirmations
+        })))),
+        None => respond_error(Error::new("Spend index is disabled")),
+    }
+}
+
+async fn get_op_returns(state: Arc<State>, prefix: &[u8], parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    let offset = unwrap!(parse_usize(parameters, "offset", 0, usize::MAX));
+    let limit = unwrap!(parse_usize(parameters, "limit", 100, 1000));
+    match state.op_returns(prefix, confirmations, offset, limit).await {
+        Some((total, entries)) => respond_ok(json!({
+            "total": total,
+            "entries": entries
+                .iter()
+                .map(|(entry, confirmations)| format_op_return(entry, *confirmations))
+                .collect::<Vec<_>>()
+        })),
+        None => respond_error(Error::new("OP_RETURN index is disabled")),
+    }
+}
+
+async fn get_op_returns_by_hash(state: Arc<State>, hash: &[u8; 32], parameters: &HashMap<String, String>) -> Response<Body> {
+    let confirmations = unwrap!(parse_confirmations(parameters, state.confirmations()));
+    match state.op_returns_by_hash(hash, confirmations).await {
+        Some(entries) => respond_ok(json!(entries
+            .iter()
+            .map(|(entry, confirmations)| format_op_return(entry, *confirmations))
+            .collect::<Vec<_>>())),
+        None => respond_error(Error::new("OP_RETURN index is disabled")),
+    }
+}
+
+async fn get_mempool_transaction(state: Arc<State>, hash: &[u8; 32]) -> Response<Body> {
+    match state.mempool_transaction(hash).await {
+        Some((entry, fee)) => {
+            let transaction = &entry.transaction.transaction;
+            let inputs: Vec<_> = transaction
+                .inputs
+                .iter()
+                .zip(&entry.spent_coins)
+                .map(|(input, coin)| {
+                    json!({
+                        "hash": reverse_hex::encode(input.txo.hash),
+                        "vout": input.txo.index,
+                        "value": format_value(coin.value),
+                        "address": coin.address().map(|address| address.to_string()),
+                        "height": format_height(coin.height)
+                    })
+                })
+                .collect();
+            let outputs: Vec<_> = transaction
+                .outputs
+                .iter()
+                .map(|output| {
+                    json!({
+                        "value": format_value(output.value),
+                        "address": Address::from_script(&output.script).ok().map(|address| address.to_string()),
+                        "script": format_script(&output.script)
+                    })
+                })
+                .collect();
+            respond_ok(json!({
+                "hash": reverse_hex::encode(hash),
+                "version": transaction.version,
+                "locktime": transaction.locktime,
+                "size": transaction.size(),
+                "vsize": fee.vsize,
+                "weight": fee.weight,
+                "fee": format_value(fee.fee),
+                "feerate": fee.feerate(),
+                "first_seen": entry.transaction.first_seen,
+                "replaceable": entry.replaceable,
+                "inputs": inputs,
+                "outputs": outputs
+            }))
+        }
+        None => respond_error(Error::new("Transaction not found in the mempool")),
+    }
+}
+
+async fn get_mempool_summary(state: Arc<State>) -> Response<Body> {
+    let (count, vsize, fees, orphans) = state.mempool_summary().await;
+    respond_ok(json!({
+        "count": count,
+        "vsize": vsize,
+        "fees": format_value(fees),
+        "orphans": orphans
+    }))
+}
+
+async fn get_transaction_fee(state: Arc<State>, hash: &[u8; 32]) -> Response<Body> {
+    match state.transaction_fee(hash).await {
+        Some((fee, height, confirmations)) => respond_ok(json!({
+            "hash": reverse_hex::encode(hash),
+            "fee": format_value(fee.fee),
+            "vsize": fee.vsize,
+            "weight": fee.weight,
+            "feerate": fee.feerate(),
+            "height": format_height(height),
+            "confirmations": confirmations
+        })),
+        None => respond_error(Error::new("Transaction not found in the last blocks or the mempool")),
+    }
+}
+
+fn format_fee_stats(stats: &BlockFeeStats) -> serde_json::Value {
+    json!({
+        "transactions": stats.transactions,
+        "total_fees": format_value(stats.total_fees),
+        "min_feerate": stats.min_feerate,
+        "median_feerate": stats.median_feerate,
+        "max_feerate": stats.max_feerate
+    })
+}
+
+async fn get_fee_stats(state: Arc<State>) -> Response<Body> {
+    let (blocks, mempool) = state.fee_stats().await;
+    respond_ok(json!({
+        "blocks": blocks
+            .iter()
+            .map(|(height, hash, stats)| {
+                let mut value = format_fee_stats(stats);
+                value["height"] = json!(height);
+                value["hash"] = json!(reverse_hex::encode(hash));
+                value
+            })
+            .collect::<Vec<_>>(),
+        "mempool": format_fee_stats(&mempool)
+    }))
+}
+
+async fn get_feerate_histogram(state: Arc<State>) -> Response<Body> {
+    let buckets = state.feerate_histogram().await;
+    respond_ok(json!({
+        "transactions": buckets.iter().map(|bucket| bucket.transactions).sum::<usize>(),
+        "vsize": buckets.iter().map(|bucket| bucket.vsize).sum::<usize>(),
+        "buckets": buckets
+            .iter()
+            .map(|bucket| json!({
+                "from": bucket.from,
+                "to": bucket.to,
+                "transactions": bucket.transactions,
+                "vsize": bucket.vsize,
+                "fees": format_value(bucket.fees)
+            }))
+            .collect::<Vec<_>>()
+    }))
+}
+
+async fn get_recommended_feerates(state: Arc<State>) -> Response<Body> {
+    let (feerates, blocks) = state.recommended_feerates().await;
+    let feerates: serde_json::Map<_, _> = FEERATE_TARGETS
+        .iter()
+        .zip(feerates)
+        .map(|(target, feerate)| (target.to_string(), json!(feerate)))
+        .collect();
+    respond_ok(json!({
+        "feerates": feerates,
+        "projected_blocks": blocks
+            .iter()
+            .map(|block| {
+                let mut value = format_fee_stats(&block.stats);
+                value["vsize"] = json!(block.vsize);
+                value
+            })
+            .collect::<Vec<_>>()
+    }))
+}
+
+async fn get_rich_list(state: Arc<State>, parameters: &HashMap<String, String>) -> Response<Body> {
+    let limit = unwrap!(parse_usize(parameters, "limit", 100, RICH_LIST_SIZE));
+    match state.rich_list(limit).await {
+        Some((height, hash, rich_list)) => respond_ok(json!({
+            "height": height,
+            "bestblock": reverse_hex::encode(hash),
+            "addresses": rich_list
+                .iter()
+                .map(|(address, balance)| json!({"address": address.to_string(), "balance": format_value(*balance)}))
+                .collect::<Vec<_>>()
+        })),
+        None => respond_error(Error::new("Balance stats are not computed yet")),
+    }
+}
+
+async fn get_balance_distribution(state: Arc<State>) -> Response<Body> {
+    match state.balance_distribution().await {
+        Some((height, hash, distribution)) => respond_ok(json!({
+            "height": height,
+            "bestblock": reverse_hex::encode(hash),
+            "buckets": distribution
+                .iter()
+                .map(|bucket| json!({
+                    "from": format_value(bucket.from),
+                    "to": format_value(bucket.to),
+                    "addresses": bucket.addresses,
+                    "balance": format_value(bucket.balance)
+                }))
+                .collect::<Vec<_>>()
+        })),
+        None => respond_error(Error::new("Balance stats are not computed yet")),
+    }
+}
+
+async fn get_utxo_set_stats(state: Arc<State>) -> Response<Body> {
+    let (height, hash, stats, addresses) = match state.utxo_set_stats().await {
+        Some(result) => result,
+        None => return respond_error(Error::new("Store is empty")),
+    };
+    let script_types: serde_json::Map<_, _> = SCRIPT_TYPES
+        .iter()
+        .zip(stats.script_types.iter())
+        .map(|(name, stats)| {
+            (
+                name.to_string(),
+                json!({"utxos": stats.count, "amount": format_value(stats.amount)}),
+            )
+        })
+        .collect();
+    respond_ok(json!({
+        "height": height,
+        "bestblock": reverse_hex::encode(hash),
+        "utxos": stats.total.count,
+        "addresses": addresses,
+        "amount": format_value(stats.total.amount),
+        "script_types": script_types,
+    }))
+}
+
+async fn get_utxo_set_muhash(state: Arc<State>) -> Response<Body> {
+    let logger = Logger::new();
+    let (height, hash, muhash) = match state.muhash().await {
+        Some(result) => result,
+        None => return respond_error(Error::new("Store is empty")),
+    };
+    let node_muhash = state.node_muhash(&hash, &logger).await;
+    let matches = node_muhash.map(|node_muhash| node_muhash == muhash);
+    if matches == Some(false) {
+        logger.log(format!(
+            "utxo set muhash {} differs from node muhash {} at height {}",
+            reverse_hex::encode(muhash),
+            reverse_hex::encode(node_muhash.unwrap()),
+            height
+        ));
+    }
+    respond_ok(json!({
+        "height": height,
+        "bestblock": reverse_hex::encode(hash),
+        "muhash": reverse_hex::encode(muhash),
+        "node_muhash": node_muhash.map(reverse_hex::encode),
+        "match": matches,
+    }))
+}
+
 fn respond(status: StatusCode, value: serde_json::Value) -> Response<Body> {
     Response::builder()
         .header("Content-Type", "application/json")
@@ -147,6 +688,10 @@ fn respond_error(error: Error) -> Response<Body> {
     respond(StatusCode::BAD_REQUEST, json!({"message": error.to_string()}))
 }
 
+fn respond_internal_error(error: Error) -> Response<Body> {
+    respond(StatusCode::INTERNAL_SERVER_ERROR, json!({"message": error.to_string()}))
+}
+
 pub fn create_server(state: Arc<State>) -> Server<State> {
     let mut server = Server::new(state);
 
@@ -160,6 +705,19 @@ pub fn create_server(state: Arc<State>) -> Server<State> {
         get_utxos(state, &address, &parameters).await
     });
 
+    server.get("/addresses/{address}/stats", |_request, parameters, _body, state| async move {
+        let address = unwrap!(parse_address_from_parameters(&parameters));
+        get_address_stats(state, &address, &parameters).await
+    });
+
+    server.get(
+        "/addresses/{address}/transactions",
+        |_request, parameters, _body, state| async move {
+            let address = unwrap!(parse_address_from_parameters(&parameters));
+            get_history(state, &address, &parameters).await
+        },
+    );
+
     server.get("/addresses/balance", |_request, parameters, _body, state| async move {
         let addresses = unwrap!(parse_addresses_from_parameters(&parameters));
         get_balance_array(state, addresses, &parameters).await
@@ -180,5 +738,79 @@ pub fn create_server(state: Arc<State>) -> Server<State> {
         get_utxos_array(state, addresses, &parameters).await
     });
 
+    server.get("/outputs/{txid}/{vout}", |_request, parameters, _body, state| async move {
+        let txo = unwrap!(parse_transaction_output_from_parameters(&parameters));
+        get_output(state, txo, &parameters).await
+    });
+
+    server.post("/outputs", |_request, parameters, body, state| async move {
+        let txos = unwrap!(parse_transaction_outputs_from_body(&body));
+        get_output_array(state, txos, &parameters).await
+    });
+
+    server.get("/outputs/{txid}/{vout}/spend", |_request, parameters, _body, state| async move {
+        let txo = unwrap!(parse_transaction_output_from_parameters(&parameters));
+        get_spender(state, &txo, &parameters).await
+    });
+
+    server.get("/scripts/{script}", |_request, parameters, _body, _state| async move {
+        match hex::decode(parameters.get("script").unwrap()) {
+            Ok(script) => respond_ok(format_script(&script)),
+            Err(_) => respond_error(Error::new("Invalid script")),
+        }
+    });
+
+    server.get("/op_returns/prefix/{prefix}", |_request, parameters, _body, state| async move {
+        let prefix = unwrap!(parse_op_return_prefix(parameters.get("prefix").unwrap()));
+        get_op_returns(state, &prefix, &parameters).await
+    });
+
+    server.get("/op_returns/hash/{hash}", |_request, parameters, _body, state| async move {
+        let hash = unwrap!(parse_payload_hash(parameters.get("hash").unwrap()));
+        get_op_returns_by_hash(state, &hash, &parameters).await
+    });
+
+    server.get("/mempool/tx/{txid}", |_request, parameters, _body, state| async move {
+        let hash = unwrap!(parse_txid(parameters.get("txid").unwrap()));
+        get_mempool_transaction(state, &hash).await
+    });
+
+    server.get("/mempool/summary", |_request, _parameters, _body, state| async move {
+        get_mempool_summary(state).await
+    });
+
+    server.get("/transactions/{txid}/fee", |_request, parameters, _body, state| async move {
+        let hash = unwrap!(parse_txid(parameters.get("txid").unwrap()));
+        get_transaction_fee(state, &hash).await
+    });
+
+    server.get("/stats/fees", |_request, _parameters, _body, state| async move {
+        get_fee_stats(state).await
+    });
+
+    server.get("/fees/histogram", |_request, _parameters, _body, state| async move {
+        get_feerate_histogram(state).await
+    });
+
+    server.get("/fees/recommended", |_request, _parameters, _body, state| async move {
+        get_recommended_feerates(state).await
+    });
+
+    server.get("/stats/richlist", |_request, parameters, _body, state| async move {
+        get_rich_list(state, &parameters).await
+    });
+
+    server.get("/stats/distribution", |_request, _parameters, _body, state| async move {
+        get_balance_distribution(state).await
+    });
+
+    server.get("/stats/utxoset", |_request, _parameters, _body, state| async move {
+        get_utxo_set_stats(state).await
+    });
+
+    server.get("/utxoset/muhash", |_request, _parameters, _body, state| async move {
+        get_utxo_set_muhash(state).await
+    });
+
     server
 }
@@ -0,0 +1,298 @@
+use crate::{hashmap::ShardedHashMap, mempool::MEMPOOL_SHARDS, BlockTrait, Transaction};
+
+/// Virtual size left to the transactions of a block, the rest being reserved for the coinbase.
+pub const BLOCK_VSIZE: usize = 999_000;
+/// Lowest feerate relayed by default by the nodes, in satoshis per virtual byte.
+pub const MIN_RELAY_FEERATE: f64 = 1.0;
+/// Numbers of blocks for which feerates are recommended.
+pub const FEERATE_TARGETS: [usize; 6] = [1, 2, 3, 6, 12, 24];
+/// Number of blocks built from the mempool to show what the next blocks may look like.
+pub const PROJECTED_BLOCKS: usize = 8;
+/// Lower bounds of the feerate buckets of the histogram, in satoshis per virtual byte.
+const FEERATE_BUCKETS: [f64; 30] = [
+    0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 12.0, 15.0, 20.0, 25.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0, 100.0, 125.0, 150.0, 200.0,
+    250.0, 300.0, 400.0, 500.0, 700.0, 1000.0, 2000.0,
+];
+
+/// Fee paid by a transaction, with the size it is charged for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionFee {
+    pub fee: u64,
+    pub vsize: usize,
+    pub weight: usize,
+}
+
+impl TransactionFee {
+    /// Fee of the transaction spending outputs worth `input_value`, `None` when its outputs are worth more.
+    pub fn new(transaction: &Transaction, input_value: u64) -> Option<Self> {
+        let output_value = transaction.outputs.iter().map(|output| output.value).sum();
+        Some(Self {
+            fee: input_value.checked_sub(output_value)?,
+            vsize: transaction.vsize(),
+            weight: transaction.weight(),
+        })
+    }
+
+    /// Fee per virtual byte, in satoshis.
+    pub fn feerate(&self) -> f64 {
+        self.fee as f64 / self.vsize as f64
+    }
+}
+
+/// Fees paid by the transactions of a block, coinbase excluded, with feerates in satoshis per virtual byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockFeeStats {
+    pub transactions: usize,
+    pub total_fees: u64,
+    pub min_feerate: f64,
+    pub median_feerate: f64,
+    pub max_feerate: f64,
+}
+
+impl BlockFeeStats {
+    pub fn new(fees: &[TransactionFee]) -> Self {
+        let mut feerates: Vec<_> = fees.iter().map(TransactionFee::feerate).collect();
+        feerates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median_feerate = match feerates.len() {
+            0 => 0.0,
+            len if len % 2 == 0 => (feerates[len / 2 - 1] + feerates[len / 2]) / 2.0,
+            len => feerates[len / 2],
+        };
+        Self {
+            transactions: fees.len(),
+            total_fees: fees.iter().map(|fee| fee.fee).sum(),
+            min_feerate: feerates.first().copied().unwrap_or(0.0),
+            median_feerate,
+            max_feerate: feerates.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+/// Mempool transactions with a feerate from `from` up to `to` excluded, the last bucket having no upper bound.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeerateBucket {
+    pub from: f64,
+    pub to: Option<f64>,
+    pub transactions: usize,
+    pub vsize: usize,
+    pub fees: u64,
+}
+
+pub fn feerate_histogram(fees: &[TransactionFee]) -> Vec<FeerateBucket> {
+    let mut buckets: Vec<_> = FEERATE_BUCKETS
+        .iter()
+        .enumerate()
+        .map(|(index, from)| FeerateBucket {
+            from: *from,
+            to: FEERATE_BUCKETS.get(index + 1).copied(),
+            transactions: 0,
+            vsize: 0,
+            fees: 0,
+        })
+        .collect();
+    for fee in fees {
+        let feerate = fee.feerate();
+        let index = FEERATE_BUCKETS.iter().rposition(|from| feerate >= *from).unwrap_or(0);
+        let bucket = &mut buckets[index];
+        bucket.transactions += 1;
+        bucket.vsize += fee.vsize;
+        bucket.fees += fee.fee;
+    }
+    buckets
+}
+
+/// Block that a miner would build from the mempool, with its virtual size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectedBlock {
+    pub vsize: usize,
+    pub stats: BlockFeeStats,
+}
+
+/// Splits the mempool into at most `count` blocks by decreasing feerate, the last block holding every transaction left.
+/// Transactions are not grouped with their ancestors, so a child paying for its parent is ranked by its own feerate.
+pub fn projected_blocks(fees: &[TransactionFee], count: usize) -> Vec<ProjectedBlock> {
+    let mut fees = fees.to_vec();
+    fees.sort_by(|a, b| b.feerate().partial_cmp(&a.feerate()).unwrap());
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut vsize = 0;
+    for (index, fee) in fees.iter().enumerate() {
+        if vsize + fee.vsize > BLOCK_VSIZE && blocks.len() + 1 < count && index > start {
+            blocks.push(ProjectedBlock {
+                vsize,
+                stats: BlockFeeStats::new(&fees[start..index]),
+            });
+            start = index;
+            vsize = 0;
+        }
+        vsize += fee.vsize;
+    }
+    if start < fees.len() {
+        blocks.push(ProjectedBlock {
+            vsize,
+            stats: BlockFeeStats::new(&fees[start..]),
+        });
+    }
+    blocks
+}
+
+/// Feerates a transaction needs to be included within each of the next `targets` blocks: the lowest feerate of the mempool
+/// transactions filling that many blocks by decreasing feerate, or the minimum relay feerate when the mempool does not fill
+/// them.
+pub fn recommended_feerates(fees: &[TransactionFee], targets: &[usize]) -> Vec<f64> {
+    let mut feerates: Vec<_> = fees.iter().map(|fee| (fee.feerate(), fee.vsize)).collect();
+    feerates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    targets
+        .iter()
+        .map(|target| {
+            let mut vsize = 0;
+            let mut feerate = MIN_RELAY_FEERATE;
+            for (transaction_feerate, transaction_vsize) in &feerates {
+                vsize += transaction_vsize;
+                if vsize > target * BLOCK_VSIZE {
+                    return feerate.max(MIN_RELAY_FEERATE);
+                }
+                feerate = *transaction_feerate;
+            }
+            MIN_RELAY_FEERATE
+        })
+        .collect()
+}
+
+/// Fees of the transactions of the blocks that are not part of the store yet and of the mempool.
+#[derive(Debug, Clone)]
+pub struct TailFees {
+    transactions: ShardedHashMap<[u8; 32], (TransactionFee, u32)>,
+    blocks: Vec<(usize, [u8; 32], BlockFeeStats)>,
+    mempool: ShardedHashMap<[u8; 32], TransactionFee>,
+}
+
+impl Default for TailFees {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TailFees {
+    pub fn new() -> Self {
+        Self {
+            transactions: ShardedHashMap::with_shards(MEMPOOL_SHARDS),
+            blocks: Vec::new(),
+            mempool: ShardedHashMap::with_shards(MEMPOOL_SHARDS),
+        }
+    }
+
+    /// Records the fees of the transactions of the block at `height`, in the order of the block.
+    pub fn add_block(&mut self, height: usize, hash: [u8; 32], fees: Vec<([u8; 32], TransactionFee)>) {
+        let stats = BlockFeeStats::new(&fees.iter().map(|(_, fee)| fee.clone()).collect::<Vec<_>>());
+        for (transaction_hash, fee) in fees {
+            self.transactions.insert(transaction_hash, (fee, height.try_into().unwrap()));
+        }
+        self.blocks.push((height, hash, stats));
+    }
+
+    /// Drops the fees of a block once it is part of the store.
+    pub fn remove_block(&mut self, block: &impl BlockTrait) {
+        let height: u32 = block.height().try_into().unwrap();
+        block.transactions(&mut |transaction| {
+            if matches!(self.transactions.get(&transaction.hash), Some((_, transaction_height)) if *transaction_height == height) {
+                self.transactions.remove(&transaction.hash);
+            }
+        });
+        self.blocks.retain(|(_, hash, _)| *hash != block.hash());
+    }
+
+    pub fn add_mempool_transaction(&mut self, hash: [u8; 32], fee: TransactionFee, height: u32) {
+        self.mempool.insert(hash, fee.clone());
+        self.transactions.insert(hash, (fee, height));
+    }
+
+    pub fn remove_mempool_transaction(&mut self, hash: &[u8; 32]) {
+        if self.mempool.remove(hash).is_some() {
+            self.transactions.remove(hash);
+        }
+    }
+
+    /// Fee of the transaction with the height of its block, `MEMPOOL_HEIGHT` for mempool transactions.
+    pub fn transaction(&self, hash: &[u8; 32]) -> Option<&(TransactionFee, u32)> {
+        self.transactions.get(hash)
+    }
+
+    /// Fee stats of each block, oldest first.
+    pub fn blocks(&self) -> &[(usize, [u8; 32], BlockFeeStats)] {
+        &self.blocks
+    }
+
+    pub fn mempool(&self) -> BlockFeeStats {
+        BlockFeeStats::new(&self.mempool_fees())
+    }
+
+    pub fn mempool_fees(&self) -> Vec<TransactionFee> {
+        self.mempool.iter().map(|(_, fee)| fee.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockFeeStats, TransactionFee};
+
+    fn fee(fee: u64, vsize: usize) -> TransactionFee {
+        TransactionFee {
+            fee,
+            vsize,
+            weight: vsize * 4,
+        }
+    }
+
+    #[test]
+    fn block_fee_stats() {
+        let stats = BlockFeeStats::new(&[fee(1000, 100), fee(500, 250), fee(3000, 200)]);
+        assert_eq!(stats.transactions, 3);
+        assert_eq!(stats.total_fees, 4500);
+        assert_eq!(stats.min_feerate, 2.0);
+        assert_eq!(stats.median_feerate, 10.0);
+        assert_eq!(stats.max_feerate, 15.0);
+        let stats = BlockFeeStats::new(&[fee(1000, 100), fee(500, 250), fee(3000, 200), fee(100, 100)]);
+        assert_eq!(stats.min_feerate, 1.0);
+        assert_eq!(stats.median_feerate, 6.0);
+        assert_eq!(BlockFeeStats::new(&[]).median_feerate, 0.0);
+    }
+
+    #[test]
+    fn feerate_histogram() {
+        let buckets = super::feerate_histogram(&[fee(50, 100), fee(100, 100), fee(250, 100), fee(4000, 200), fee(300000, 100)]);
+        let counts: Vec<_> = buckets
+            .iter()
+            .filter(|bucket| bucket.transactions > 0)
+            .map(|bucket| (bucket.from, bucket.transactions))
+            .collect();
+        assert_eq!(counts, vec![(0.0, 1), (1.0, 1), (2.0, 1), (20.0, 1), (2000.0, 1)]);
+        assert_eq!(buckets.last().unwrap().to, None);
+        assert_eq!(buckets[1].to, Some(2.0));
+        assert_eq!(buckets[1].vsize, 100);
+        assert_eq!(buckets[1].fees, 100);
+    }
+
+    #[test]
+    fn projected_blocks() {
+        let fees: Vec<_> = (0..30).map(|index| fee(100_000 * (index + 1), 100_000)).collect();
+        let blocks = super::projected_blocks(&fees, 2);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].vsize, 900_000);
+        assert_eq!(blocks[0].stats.max_feerate, 30.0);
+        assert_eq!(blocks[0].stats.min_feerate, 22.0);
+        assert_eq!(blocks[1].stats.transactions, 21);
+        assert_eq!(blocks[1].stats.max_feerate, 21.0);
+        assert_eq!(super::projected_blocks(&fees, 8).len(), 4);
+        assert_eq!(super::projected_blocks(&[], 8), vec![]);
+    }
+
+    #[test]
+    fn recommended_feerates() {
+        let fees: Vec<_> = (0..30).map(|index| fee(100_000 * (index + 1), 100_000)).collect();
+        assert_eq!(super::recommended_feerates(&fees, &[1, 2, 3, 4]), vec![22.0, 12.0, 2.0, 1.0]);
+        let fees: Vec<_> = (0..30).map(|index| fee(index, 1000)).collect();
+        assert_eq!(super::recommended_feerates(&fees, &[1]), vec![1.0]);
+        assert_eq!(super::recommended_feerates(&[], &[1]), vec![1.0]);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::TryInto;
 use sha2::{Digest, Sha256};
+use std::{error, fmt};
 
 pub struct Hasher {
     hasher: Sha256,
@@ -22,6 +23,62 @@ impl Hasher {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The field needs `length` bytes but the buffer ends before.
+    UnexpectedEnd { length: u64 },
+    /// The field holds a value that cannot be used there.
+    InvalidValue(u64),
+    /// The value ends before the buffer does.
+    TrailingBytes { length: usize },
+}
+
+/// Error returned when a buffer does not hold what is expected, with the offset and name of the field that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub field: &'static str,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn new(offset: usize, field: &'static str, kind: ParseErrorKind) -> Self {
+        Self { offset, field, kind }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnexpectedEnd { length } => write!(
+                f,
+                "unexpected end of buffer reading {} bytes of {} at offset {}",
+                length, self.field, self.offset
+            ),
+            ParseErrorKind::InvalidValue(value) => write!(f, "invalid {} {} at offset {}", self.field, value, self.offset),
+            ParseErrorKind::TrailingBytes { length } => {
+                write!(f, "{} trailing bytes after {} at offset {}", length, self.field, self.offset)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+pub trait ParseResultExt {
+    /// Names the field that failed, in place of the generic name given by the reader.
+    fn field(self, field: &'static str) -> Self;
+}
+
+impl<T> ParseResultExt for ParseResult<T> {
+    fn field(self, field: &'static str) -> Self {
+        self.map_err(|error| ParseError { field, ..error })
+    }
+}
+
+#[derive(Clone)]
 pub struct HashingBufferReader<'a> {
     buffer: &'a [u8],
     offset: usize,
@@ -39,77 +96,200 @@ impl<'a> HashingBufferReader<'a> {
     }
 
     #[inline(always)]
-    pub fn read_i32_le(&mut self, hasher: &mut Option<&mut Hasher>) -> i32 {
-        i32::from_le_bytes(self.read_buffer(4, hasher).try_into().unwrap())
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.offset
     }
 
     #[inline(always)]
-    pub fn read_bool(&mut self, hasher: &mut Option<&mut Hasher>) -> bool {
-        match self.read_buffer(1, hasher)[0] {
-            0 => false,
-            _ => true,
-        }
+    pub fn read_i32_le(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<i32> {
+        Ok(i32::from_le_bytes(self.read_array(hasher).field("i32")?))
+    }
+
+    #[inline(always)]
+    pub fn read_bool(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<bool> {
+        Ok(self.read_u8(hasher).field("bool")? != 0)
     }
 
     #[inline(always)]
-    pub fn read_u8(&mut self, hasher: &mut Option<&mut Hasher>) -> u8 {
-        self.read_buffer(1, hasher)[0]
+    pub fn read_u8(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<u8> {
+        Ok(self.read_buffer(1, hasher).field("u8")?[0])
     }
 
     #[inline(always)]
-    pub fn read_u16_le(&mut self, hasher: &mut Option<&mut Hasher>) -> u16 {
-        u16::from_le_bytes(self.read_buffer(2, hasher).try_into().unwrap())
+    pub fn read_u16_le(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<u16> {
+        Ok(u16::from_le_bytes(self.read_array(hasher).field("u16")?))
     }
 
     #[inline(always)]
-    pub fn read_u32_le(&mut self, hasher: &mut Option<&mut Hasher>) -> u32 {
-        u32::from_le_bytes(self.read_buffer(4, hasher).try_into().unwrap())
+    pub fn read_u32_le(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<u32> {
+        Ok(u32::from_le_bytes(self.read_array(hasher).field("u32")?))
     }
 
     #[inline(always)]
-    pub fn read_u64_le(&mut self, hasher: &mut Option<&mut Hasher>) -> u64 {
-        u64::from_le_bytes(self.read_buffer(8, hasher).try_into().unwrap())
+    pub fn read_u64_le(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<u64> {
+        Ok(u64::from_le_bytes(self.read_array(hasher).field("u64")?))
     }
 
     #[inline(always)]
-    pub fn read_var_int_le(&mut self, hasher: &mut Option<&mut Hasher>) -> u64 {
-        match self.read_u8(hasher) {
-            0xFD => self.read_u16_le(hasher).into(),
-            0xFE => self.read_u32_le(hasher).into(),
-            0xFF => self.read_u64_le(hasher).into(),
+    pub fn read_var_int_le(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<u64> {
+        let value = match self.read_u8(hasher)? {
+            0xFD => self.read_u16_le(hasher)?.into(),
+            0xFE => self.read_u32_le(hasher)?.into(),
+            0xFF => self.read_u64_le(hasher)?,
             byte => byte.into(),
+        };
+        Ok(value)
+    }
+
+    /// Reads the number of items that follow, each taking at least `item_size` bytes, rejecting counts the rest of the
+    /// buffer cannot hold.
+    #[inline(always)]
+    pub fn read_count_le(&mut self, item_size: usize, hasher: &mut Option<&mut Hasher>) -> ParseResult<usize> {
+        let offset = self.offset;
+        let count = self.read_var_int_le(hasher).field("count")?;
+        match usize::try_from(count) {
+            Ok(length) if length.saturating_mul(item_size) <= self.remaining() => Ok(length),
+            _ => Err(ParseError::new(offset, "count", ParseErrorKind::InvalidValue(count))),
         }
     }
 
     #[inline(always)]
-    pub fn read_buffer(&mut self, length: usize, hasher: &mut Option<&mut Hasher>) -> &'a [u8] {
+    pub fn read_b128_var_int(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<u64> {
+        let offset = self.offset;
+        let mut value: u64 = 0;
+        loop {
+            let byte = self.read_u8(hasher).field("var int")?;
+            if value > u64::MAX >> 7 {
+                return Err(ParseError::new(offset, "var int", ParseErrorKind::InvalidValue(value)));
+            }
+            value = (value << 7) | u64::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            value = value
+                .checked_add(1)
+                .ok_or_else(|| ParseError::new(offset, "var int", ParseErrorKind::InvalidValue(value)))?;
+        }
+    }
+
+    /// Reads a var int that must fit in a narrower integer type.
+    #[inline(always)]
+    pub fn read_b128_var_int_as<T: TryFrom<u64>>(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<T> {
+        let offset = self.offset;
+        let value = self.read_b128_var_int(hasher)?;
+        T::try_from(value).map_err(|_| ParseError::new(offset, "var int", ParseErrorKind::InvalidValue(value)))
+    }
+
+    #[inline(always)]
+    pub fn read_buffer(&mut self, length: usize, hasher: &mut Option<&mut Hasher>) -> ParseResult<&'a [u8]> {
+        if length > self.remaining() {
+            return Err(ParseError::new(
+                self.offset,
+                "buffer",
+                ParseErrorKind::UnexpectedEnd {
+                    length: length.try_into().unwrap(),
+                },
+            ));
+        }
         let offset = self.offset;
         self.offset += length;
         let buffer = &self.buffer[offset..self.offset];
         if let Some(hasher) = hasher {
             hasher.update(buffer);
         }
-        buffer
+        Ok(buffer)
     }
 
     #[inline(always)]
-    pub fn read_var_buffer_le(&mut self, hasher: &mut Option<&mut Hasher>) -> Vec<u8> {
-        let length = self.read_var_int_le(hasher);
-        self.read_buffer(length.try_into().unwrap(), hasher).to_vec()
+    fn read_array<const N: usize>(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<[u8; N]> {
+        Ok(self.read_buffer(N, hasher)?.try_into().unwrap())
     }
 
     #[inline(always)]
-    pub fn read_hash(&mut self, hasher: &mut Option<&mut Hasher>) -> [u8; 32] {
-        self.read_buffer(32, hasher).try_into().unwrap()
+    pub fn read_var_buffer_le(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<Vec<u8>> {
+        let length = self.read_count_le(1, hasher).field("buffer length")?;
+        Ok(self.read_buffer(length, hasher)?.to_vec())
     }
 
     #[inline(always)]
-    pub fn peek_u8(&self) -> u8 {
-        self.buffer[self.offset]
+    pub fn read_hash(&mut self, hasher: &mut Option<&mut Hasher>) -> ParseResult<[u8; 32]> {
+        self.read_array(hasher).field("hash")
     }
 
     #[inline(always)]
-    pub fn skip(&mut self, length: usize, hasher: &mut Option<&mut Hasher>) {
-        self.read_buffer(length, hasher);
+    pub fn peek_u8(&self) -> ParseResult<u8> {
+        match self.buffer.get(self.offset) {
+            Some(byte) => Ok(*byte),
+            None => Err(ParseError::new(self.offset, "u8", ParseErrorKind::UnexpectedEnd { length: 1 })),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_at_end(&self) -> bool {
+        self.offset == self.buffer.len()
+    }
+
+    /// Fails when bytes are left after the value named `field`.
+    #[inline(always)]
+    pub fn expect_end(&self, field: &'static str) -> ParseResult<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            length => Err(ParseError::new(self.offset, field, ParseErrorKind::TrailingBytes { length })),
+        }
+    }
+
+    #[inline(always)]
+    pub fn skip(&mut self, length: usize, hasher: &mut Option<&mut Hasher>) -> ParseResult<()> {
+        self.read_buffer(length, hasher)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashingBufferReader, ParseError, ParseErrorKind};
+
+    #[test]
+    fn read_past_end() {
+        let mut reader = HashingBufferReader::new(&[1, 2, 3, 4, 5]);
+        assert_eq!(reader.read_u32_le(&mut None), Ok(0x04030201));
+        assert_eq!(
+            reader.read_u32_le(&mut None),
+            Err(ParseError::new(4, "u32", ParseErrorKind::UnexpectedEnd { length: 4 }))
+        );
+        assert_eq!(reader.offset(), 4);
+        assert_eq!(reader.read_u8(&mut None), Ok(5));
+        assert_eq!(
+            reader.peek_u8(),
+            Err(ParseError::new(5, "u8", ParseErrorKind::UnexpectedEnd { length: 1 }))
+        );
+    }
+
+    #[test]
+    fn read_count_le() {
+        assert_eq!(HashingBufferReader::new(&[2, 0, 0]).read_count_le(1, &mut None), Ok(2));
+        assert_eq!(
+            HashingBufferReader::new(&[2, 0, 0]).read_count_le(2, &mut None),
+            Err(ParseError::new(0, "count", ParseErrorKind::InvalidValue(2)))
+        );
+        assert_eq!(
+            HashingBufferReader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]).read_count_le(1, &mut None),
+            Err(ParseError::new(0, "count", ParseErrorKind::InvalidValue(u64::MAX)))
+        );
+    }
+
+    #[test]
+    fn read_b128_var_int() {
+        assert_eq!(HashingBufferReader::new(&[0x80, 0x00]).read_b128_var_int(&mut None), Ok(128));
+        assert_eq!(
+            HashingBufferReader::new(&[0xFF; 11])
+                .read_b128_var_int(&mut None)
+                .map_err(|error| error.field),
+            Err("var int")
+        );
+        assert_eq!(
+            HashingBufferReader::new(&[0x82, 0xFF, 0x00]).read_b128_var_int_as::<u16>(&mut None),
+            Err(ParseError::new(0, "var int", ParseErrorKind::InvalidValue(0x10000)))
+        );
     }
 }
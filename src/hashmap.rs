@@ -1,7 +1,11 @@
-use crate::{Address, HashMap, TransactionOutput, TryInto};
-use std::hash::{BuildHasher, Hasher};
+use crate::{Address, Arc, HashMap, TransactionOutput, TryInto};
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+};
 
-struct U64Hasher {
+pub struct U64Hasher {
     state: u64,
 }
 
@@ -32,7 +36,8 @@ impl Hasher for U64Hasher {
     }
 }
 
-struct BuildU64Hasher;
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BuildU64Hasher;
 
 impl BuildHasher for BuildU64Hasher {
     type Hasher = U64Hasher;
@@ -43,7 +48,7 @@ impl BuildHasher for BuildU64Hasher {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct U64HashMap<K, V> {
     hashmap: HashMap<K, V, BuildU64Hasher>,
 }
@@ -112,6 +117,290 @@ impl<'a, K, V> IntoIterator for &'a U64HashMap<K, V> {
     }
 }
 
+/// Number of entries per shard aimed at by `ShardedHashMap::with_capacity`.
+const SHARD_CAPACITY: usize = 128;
+
+const MAX_SHARDS: usize = 1 << 20;
+
+/// Bits of the hash selecting the shard. The tables of the shards use the low and the top bits of the hash.
+const SHARD_SHIFT: u32 = 32;
+
+/// Hash map split into shards shared with its clones, so that a clone is cheap and modifying it only copies the shards it
+/// modifies.
+#[derive(Debug, Clone)]
+pub struct ShardedHashMap<K, V, S = BuildU64Hasher> {
+    shards: Vec<Arc<HashMap<K, V, S>>>,
+    len: usize,
+    hasher: S,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone + Default> ShardedHashMap<K, V, S> {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::with_shards(1)
+    }
+
+    /// Map with as many shards as needed for `capacity` entries, each with room for its share of them.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let shards = (capacity / SHARD_CAPACITY).next_power_of_two().min(MAX_SHARDS);
+        let hasher = S::default();
+        Self {
+            shards: (0..shards)
+                .map(|_| Arc::new(HashMap::with_capacity_and_hasher(capacity / shards, hasher.clone())))
+                .collect(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    /// Empty map with `shards` shards, which must be a power of two.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards.is_power_of_two() && shards <= MAX_SHARDS);
+        let hasher = S::default();
+        Self {
+            shards: (0..shards).map(|_| Arc::new(HashMap::with_hasher(hasher.clone()))).collect(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    #[inline(always)]
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        (self.hasher.hash_one(key) >> SHARD_SHIFT) as usize & (self.shards.len() - 1)
+    }
+
+    #[inline(always)]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let shard = self.shard(&key);
+        let previous = Arc::make_mut(&mut self.shards[shard]).insert(key, value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    #[inline(always)]
+    pub fn remove<Q: Eq + Hash + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let shard = self.shard(key);
+        if !self.shards[shard].contains_key(key) {
+            return None;
+        }
+        self.len -= 1;
+        Arc::make_mut(&mut self.shards[shard]).remove(key)
+    }
+
+    #[inline(always)]
+    pub fn get<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.shards[self.shard(key)].get(key)
+    }
+
+    #[inline(always)]
+    pub fn contains_key<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.shards[self.shard(key)].contains_key(key)
+    }
+
+    #[inline(always)]
+    pub fn get_mut<Q: Eq + Hash + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        let shard = self.shard(key);
+        if !self.shards[shard].contains_key(key) {
+            return None;
+        }
+        Arc::make_mut(&mut self.shards[shard]).get_mut(key)
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    /// Splits the shards into at most `count` groups, passed to `fill` with the router giving the group of each key, so
+    /// that different threads can fill different groups.
+    pub fn fill_groups<R>(&mut self, count: usize, fill: impl FnOnce(ShardRouter<S>, Vec<ShardGroup<'_, K, V, S>>) -> R) -> R {
+        let group_shards = self.shards.len().div_ceil(count);
+        let mask = self.shards.len() - 1;
+        let router = ShardRouter {
+            hasher: self.hasher.clone(),
+            mask,
+            group_shards,
+        };
+        let groups = self
+            .shards
+            .chunks_mut(group_shards)
+            .enumerate()
+            .map(|(index, shards)| ShardGroup {
+                shards,
+                first: index * group_shards,
+                mask,
+                hasher: self.hasher.clone(),
+            })
+            .collect();
+        let result = fill(router, groups);
+        self.len = self.shards.iter().map(|shard| shard.len()).sum();
+        result
+    }
+}
+
+/// Gives the group of the shard of each key of a map split by `ShardedHashMap::fill_groups`.
+pub struct ShardRouter<S> {
+    hasher: S,
+    mask: usize,
+    group_shards: usize,
+}
+
+impl<S: BuildHasher> ShardRouter<S> {
+    #[inline(always)]
+    pub fn group<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        ((self.hasher.hash_one(key) >> SHARD_SHIFT) as usize & self.mask) / self.group_shards
+    }
+}
+
+/// Shards of a map split by `ShardedHashMap::fill_groups`, which only accept the keys routed to them.
+pub struct ShardGroup<'a, K, V, S> {
+    shards: &'a mut [Arc<HashMap<K, V, S>>],
+    first: usize,
+    mask: usize,
+    hasher: S,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone> ShardGroup<'_, K, V, S> {
+    #[inline(always)]
+    fn shard(&self, key: &K) -> usize {
+        ((self.hasher.hash_one(key) >> SHARD_SHIFT) as usize & self.mask) - self.first
+    }
+
+    #[inline(always)]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let shard = self.shard(&key);
+        Arc::make_mut(&mut self.shards[shard]).insert(key, value)
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let shard = self.shard(key);
+        if !self.shards[shard].contains_key(key) {
+            return None;
+        }
+        Arc::make_mut(&mut self.shards[shard]).get_mut(key)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone + Default> Default for ShardedHashMap<K, V, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> IntoIterator for ShardedHashMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter =
+        std::iter::FlatMap<std::vec::IntoIter<Arc<HashMap<K, V, S>>>, HashMap<K, V, S>, fn(Arc<HashMap<K, V, S>>) -> HashMap<K, V, S>>;
+    #[inline(always)]
+    fn into_iter(self) -> Self::IntoIter {
+        self.shards.into_iter().flat_map(Arc::unwrap_or_clone)
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a ShardedHashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = std::iter::FlatMap<
+        std::slice::Iter<'a, Arc<HashMap<K, V, S>>>,
+        &'a HashMap<K, V, S>,
+        fn(&'a Arc<HashMap<K, V, S>>) -> &'a HashMap<K, V, S>,
+    >;
+    #[inline(always)]
+    fn into_iter(self) -> Self::IntoIter {
+        self.shards.iter().flat_map(|shard| &**shard)
+    }
+}
+
 pub type TransactionOutputHashMap<V> = U64HashMap<TransactionOutput, V>;
 
 pub type AddressHashMap<V> = U64HashMap<Address, V>;
+
+pub type ShardedTransactionOutputHashMap<V> = ShardedHashMap<TransactionOutput, V>;
+
+pub type ShardedAddressHashMap<V> = ShardedHashMap<Address, V>;
+
+/// Sharded map for keys that `U64Hasher` cannot hash, such as byte strings shorter than 12 bytes.
+pub type RandomShardedHashMap<K, V> = ShardedHashMap<K, V, RandomState>;
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedTransactionOutputHashMap;
+    use crate::TransactionOutput;
+
+    fn txo(index: u32) -> TransactionOutput {
+        let mut hash = [0; 32];
+        hash[4..8].copy_from_slice(&index.to_le_bytes());
+        TransactionOutput::new(hash, index)
+    }
+
+    #[test]
+    fn clone() {
+        let mut map = ShardedTransactionOutputHashMap::with_capacity(10_000);
+        for index in 0..1000 {
+            map.insert(txo(index), index);
+        }
+        let clone = map.clone();
+        map.remove(&txo(0));
+        *map.get_mut(&txo(1)).unwrap() += 1;
+        map.insert(txo(1000), 1000);
+        assert_eq!((map.len(), clone.len()), (1000, 1000));
+        assert_eq!((map.get(&txo(0)), clone.get(&txo(0))), (None, Some(&0)));
+        assert_eq!((map.get(&txo(1)), clone.get(&txo(1))), (Some(&2), Some(&1)));
+        assert_eq!(clone.get(&txo(1000)), None);
+        assert_eq!(map.iter().count(), 1000);
+        assert_eq!(map.remove(&txo(0)), None);
+        assert_eq!(map.len(), 1000);
+    }
+
+    #[test]
+    fn fill_groups() {
+        let mut map = ShardedTransactionOutputHashMap::with_capacity(10_000);
+        map.insert(txo(0), 1);
+        map.fill_groups(3, |router, groups| {
+            assert_eq!(groups.len(), 3);
+            std::thread::scope(|scope| {
+                for (group, mut shards) in groups.into_iter().enumerate() {
+                    let router = &router;
+                    scope.spawn(move || {
+                        for index in (0..1000).filter(|index| router.group(&txo(*index)) == group) {
+                            match shards.get_mut(&txo(index)) {
+                                Some(value) => *value += index,
+                                None => assert_eq!(shards.insert(txo(index), index), None),
+                            }
+                        }
+                    });
+                }
+            });
+        });
+        assert_eq!(map.len(), 1000);
+        assert_eq!((map.get(&txo(0)), map.get(&txo(999))), (Some(&1), Some(&999)));
+    }
+}
@@ -1,12 +1,19 @@
+use crate::store::MEMPOOL_HEIGHT;
 use crate::{
-    reverse_hex, AugmentedTransactionStore, Block, BlockTrait, Client, HashMap, IndexedStore, Logger, Store, Transaction,
-    TransactionStoreAugmentation, TransactionStoreBackendTrait, VecDeque,
+    fees::TailFees,
+    mempool::{self, MempoolSnapshot, MempoolTransaction, Replacement, MAX_REPLACEMENTS},
+    reverse_hex, Arc, AugmentedTransactionStore, Block, BlockTrait, Client, HashMap, HashSet, IndexedStore, Logger, Transaction,
+    TransactionStoreBackendTrait, VecDeque,
 };
 
 #[derive(Debug)]
 struct Mempool {
-    pub transactions: HashMap<[u8; 32], Transaction>,
+    pub transactions: HashMap<[u8; 32], Arc<MempoolTransaction>>,
     pub hashes: Vec<[u8; 32]>,
+    /// Mempool sequence of the node as of the last update.
+    pub sequence: Option<u64>,
+    /// Last transactions replaced in the mempool, oldest first.
+    pub replacements: VecDeque<Arc<Replacement>>,
 }
 
 impl Mempool {
@@ -14,61 +21,86 @@ impl Mempool {
         Self {
             transactions: HashMap::new(),
             hashes: Vec::new(),
+            sequence: None,
+            replacements: VecDeque::new(),
         }
     }
 
-    pub fn transactions<F: FnMut(&Transaction)>(&self, callback: &mut F) {
-        for hash in self.hashes.iter() {
-            if let Some(transaction) = self.transactions.get(hash) {
-                callback(transaction);
-            }
-        }
+    /// Transactions with each one after the transactions it spends among them.
+    pub fn transactions(&self) -> impl Iterator<Item = &Arc<MempoolTransaction>> {
+        self.hashes.iter().filter_map(|hash| self.transactions.get(hash))
     }
 }
 
+/// How the blocks changed during an update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlocksUpdate {
+    Unchanged,
+    /// Blocks were added after the last one.
+    Extended(usize),
+    /// Some blocks were replaced by others.
+    Reorganized,
+}
+
 struct BlockUpdater<'a> {
     store: &'a IndexedStore,
+    confirmed_blocks: &'a [Block],
     blocks: &'a mut VecDeque<Block>,
 }
 
 impl<'a> BlockUpdater<'a> {
     fn height(&self) -> usize {
-        self.store.height() + self.blocks.len()
+        self.store.height() + self.confirmed_blocks.len() + self.blocks.len()
     }
 
     fn last_block_hash(&self) -> [u8; 32] {
-        match self.blocks.len() {
-            0 => self.store.last_block_hash().unwrap(),
-            n => self.blocks[n - 1].hash(),
+        match (self.blocks.back(), self.confirmed_blocks.last()) {
+            (Some(block), _) | (None, Some(block)) => block.hash(),
+            (None, None) => self.store.last_block_hash().unwrap(),
         }
     }
 
-    fn add_block(&mut self, block: Block) {
+    /// Adds the block after the last one when it follows it, otherwise drops the last one. Returns whether the block was
+    /// added.
+    fn add_block(&mut self, block: Block) -> bool {
         if block.previous_block_hash() == self.last_block_hash() {
             self.blocks.push_back(block);
+            true
         } else {
             assert!(self.blocks.pop_back().is_some());
+            false
         }
     }
 
     async fn next_block(&self, client: &Client, logger: &Logger) -> Option<Block> {
         let hash = client.getblockhash(self.height(), logger).await?;
-        let buffer = client.getblock(hash, logger).await?;
-        let block = Block::new(&buffer, self.height());
-        Some(block)
+        let buffer = client.getblock(&hash, logger).await?;
+        // The block is fetched again on the next update.
+        match Block::new(&buffer, self.height()) {
+            Ok(block) => Some(block),
+            Err(error) => {
+                logger.log(format!("Could not parse block {}: {}", reverse_hex::encode(hash), error));
+                None
+            }
+        }
     }
 
-    async fn update(&mut self, client: &Client, logger: &Logger) -> bool {
-        let mut updated = false;
+    async fn update(&mut self, client: &Client, logger: &Logger) -> BlocksUpdate {
+        let mut update = BlocksUpdate::Unchanged;
         while let Some(block) = self.next_block(client, logger).await {
-            updated = true;
-            self.add_block(block);
+            update = match (self.add_block(block), update) {
+                (false, _) | (_, BlocksUpdate::Reorganized) => BlocksUpdate::Reorganized,
+                (true, BlocksUpdate::Extended(count)) => BlocksUpdate::Extended(count + 1),
+                (true, BlocksUpdate::Unchanged) => BlocksUpdate::Extended(1),
+            };
         }
-        updated
+        update
     }
 }
 
 pub struct LastBlocks {
+    /// Blocks with enough confirmations that are not part of the store yet.
+    confirmed_blocks: Vec<Block>,
     blocks: VecDeque<Block>,
     mempool: Mempool,
 }
@@ -76,76 +108,163 @@ pub struct LastBlocks {
 impl LastBlocks {
     pub fn new() -> Self {
         Self {
+            confirmed_blocks: Vec::new(),
             blocks: VecDeque::new(),
             mempool: Mempool::new(),
         }
     }
 
-    fn augmentation(&self, store: &Store<impl TransactionStoreBackendTrait>, count: usize) -> TransactionStoreAugmentation {
-        assert!(count <= self.blocks.len());
-        let mut augmented_store = AugmentedTransactionStore::new(store.transaction_store());
-        for i in 0..count + 1 {
-            if i < self.blocks.len() {
-                augmented_store.add_block(&self.blocks[i]);
-            } else {
-                self.mempool.transactions(&mut |transaction| {
-                    if augmented_store.can_add_transaction(transaction) {
-                        augmented_store.add_transaction(transaction)
-                    }
-                });
-            }
-        }
-        augmented_store.take_augmentation()
-    }
-
     pub fn len(&self) -> usize {
         self.blocks.len() + 1
     }
 
-    pub fn pop(&mut self, confirmations: usize) -> Vec<Block> {
-        let mut blocks = Vec::new();
+    /// Moves the blocks with `confirmations` confirmations to the confirmed blocks.
+    pub fn confirm(&mut self, confirmations: usize) {
         while self.blocks.len() > confirmations - 1 {
-            blocks.push(self.blocks.pop_front().unwrap());
+            self.confirmed_blocks.push(self.blocks.pop_front().unwrap());
         }
-        blocks
     }
 
-    pub fn last_augmentation(&self, store: &Store<impl TransactionStoreBackendTrait>) -> TransactionStoreAugmentation {
-        self.augmentation(store, self.blocks.len())
+    pub fn confirmed_blocks(&self) -> &[Block] {
+        &self.confirmed_blocks
     }
 
-    pub fn augmentations(&self, store: &Store<impl TransactionStoreBackendTrait>) -> Vec<TransactionStoreAugmentation> {
-        let mut augmentations = Vec::new();
-        for i in 0..self.blocks.len() + 1 {
-            augmentations.push(self.augmentation(store, i));
-        }
-        augmentations
+    pub fn take_confirmed_blocks(&mut self) -> Vec<Block> {
+        std::mem::take(&mut self.confirmed_blocks)
+    }
+
+    /// Blocks that do not have enough confirmations yet, oldest first.
+    pub fn blocks(&self) -> &VecDeque<Block> {
+        &self.blocks
+    }
+
+    /// Mempool transactions with each one after the transactions it spends among them.
+    pub fn mempool_transactions(&self) -> impl Iterator<Item = &Arc<MempoolTransaction>> {
+        self.mempool.transactions()
     }
 
-    pub async fn update_blocks(&mut self, store: &IndexedStore, client: &Client, logger: &Logger) -> bool {
+    pub async fn update_blocks(&mut self, store: &IndexedStore, client: &Client, logger: &Logger) -> BlocksUpdate {
         let mut updater = BlockUpdater {
             store,
+            confirmed_blocks: &self.confirmed_blocks,
             blocks: &mut self.blocks,
         };
         updater.update(client, logger).await
     }
 
-    pub async fn update_mempool(&mut self, client: &Client, logger: &Logger) {
-        let hashes = client.getrawmempool(logger).await;
-        let mut transactions = HashMap::new();
+    /// Requests the hashes of all the mempool transactions, then fetches the ones missing from the previous list and drops
+    /// the ones no longer listed, unless the mempool sequence did not change. Returns the transactions that entered the
+    /// mempool, parents first, with the hashes of the ones that left it.
+    pub async fn update_mempool(&mut self, client: &Client, logger: &Logger) -> (Vec<Arc<MempoolTransaction>>, Vec<[u8; 32]>) {
+        let (hashes, sequence) = client.getrawmempool(logger).await;
+        if self.mempool.sequence == Some(sequence) {
+            return (Vec::new(), Vec::new());
+        }
+        let current: HashSet<_> = hashes.iter().collect();
+        let (mut kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.mempool.hashes)
+            .into_iter()
+            .partition(|hash| current.contains(hash));
+        let removed: Vec<_> = removed.iter().map(|hash| self.mempool.transactions.remove(hash).unwrap()).collect();
+        let mut added = Vec::new();
+        let mut complete = true;
         for hash in &hashes {
-            match self.mempool.transactions.remove(hash) {
-                Some(transaction) => {
-                    transactions.insert(*hash, transaction);
-                }
-                None => match client.getrawtransaction(&hash, logger).await {
-                    Some(transaction) => {
-                        transactions.insert(*hash, transaction);
+            if self.mempool.transactions.contains_key(hash) {
+                continue;
+            }
+            match client.getrawtransaction(hash, logger).await {
+                // The transaction is left out of the mempool, it is fetched again on the next update.
+                Some(buffer) => match Transaction::from_slice(&buffer) {
+                    Ok(transaction) => {
+                        added.push(*hash);
+                        self.mempool
+                            .transactions
+                            .insert(*hash, Arc::new(MempoolTransaction::new(transaction)));
+                    }
+                    Err(error) => {
+                        complete = false;
+                        logger.log(format!("Could not parse transaction {}: {}", reverse_hex::encode(hash), error));
                     }
-                    None => logger.log(format!("Could not get transaction {}", reverse_hex::encode(hash))),
                 },
+                None => {
+                    complete = false;
+                    logger.log(format!("Could not get transaction {}", reverse_hex::encode(hash)));
+                }
+            }
+        }
+        // The sequence is only kept when every transaction could be fetched, so that the missing ones are fetched again.
+        self.mempool.sequence = complete.then_some(sequence);
+        let transactions = &self.mempool.transactions;
+        let added_hashes: HashSet<_> = added.iter().copied().collect();
+        let added = mempool::sort_topologically(&added, |hash| {
+            let inputs = &transactions[hash].transaction.inputs;
+            inputs
+                .iter()
+                .map(|input| input.txo.hash)
+                .filter(|hash| added_hashes.contains(hash))
+                .collect()
+        });
+        kept.extend(&added);
+        self.mempool.hashes = kept;
+        let removed_transactions: Vec<_> = removed.iter().map(|mempool_transaction| &mempool_transaction.transaction).collect();
+        let added_transactions: Vec<_> = added.iter().map(|hash| &transactions[hash].transaction).collect();
+        for replacement in mempool::find_replacements(&removed_transactions, &added_transactions) {
+            logger.log(format!(
+                "transaction {} replaced by {}",
+                reverse_hex::encode(replacement.hash),
+                reverse_hex::encode(replacement.replaced_by)
+            ));
+            self.mempool.replacements.push_back(Arc::new(replacement));
+        }
+        self.mempool
+            .replacements
+            .retain(|replacement| !transactions.contains_key(&replacement.hash));
+        while self.mempool.replacements.len() > MAX_REPLACEMENTS {
+            self.mempool.replacements.pop_front();
+        }
+        let added = added.iter().map(|hash| Arc::clone(&transactions[hash])).collect();
+        let removed = removed
+            .iter()
+            .map(|mempool_transaction| mempool_transaction.transaction.hash)
+            .collect();
+        (added, removed)
+    }
+
+    /// Last transactions replaced in the mempool, oldest first.
+    pub fn replacements(&self) -> Vec<Arc<Replacement>> {
+        self.mempool.replacements.iter().cloned().collect()
+    }
+}
+
+/// Adds the mempool transactions whose outputs are all known, trying again the ones that cannot be added yet as long as others
+/// are added, recording their fees and their mempool entries, and returns the ones left out.
+pub fn add_mempool_transactions(
+    augmented_store: &mut AugmentedTransactionStore<impl TransactionStoreBackendTrait>,
+    mut pending: Vec<Arc<MempoolTransaction>>,
+    fees: &mut TailFees,
+    mempool: &mut MempoolSnapshot,
+) -> Vec<Arc<MempoolTransaction>> {
+    loop {
+        let count = pending.len();
+        pending.retain(|mempool_transaction| {
+            let transaction = &mempool_transaction.transaction;
+            if !augmented_store.can_add_transaction(transaction) {
+                return true;
+            }
+            if let Some(fee) = augmented_store.transaction_fee(transaction) {
+                fees.add_mempool_transaction(transaction.hash, fee, MEMPOOL_HEIGHT);
             }
+            let backend = augmented_store.backend();
+            let spent_coins = transaction
+                .inputs
+                .iter()
+                .map(|input| backend.transaction_output(&input.txo).unwrap().clone())
+                .collect();
+            mempool.add(Arc::clone(mempool_transaction), spent_coins);
+            augmented_store.add_transaction(transaction, MEMPOOL_HEIGHT);
+            false
+        });
+        if pending.len() == count {
+            return pending;
         }
-        self.mempool = Mempool { hashes, transactions };
     }
 }
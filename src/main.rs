@@ -1,4 +1,5 @@
 pub mod address;
+pub mod balance_stats;
 pub mod base58;
 pub mod base58_check;
 pub mod base64;
@@ -9,19 +10,28 @@ pub mod block;
 pub mod block_file_reader;
 pub mod buffer_writer;
 pub mod chronometer;
+pub mod chunked_vec;
 pub mod client;
+pub mod commands;
+pub mod compression;
 pub mod configuration;
 pub mod create_server;
 pub mod error;
 pub mod executor;
+pub mod fees;
 pub mod hashing_buffer_reader;
 pub mod hashmap;
 pub mod hex;
 pub mod last_blocks;
 pub mod logger;
+pub mod mempool;
+pub mod muhash;
 pub mod reverse_hex;
+pub mod script;
+pub mod secp256k1;
 pub mod sequential_thread_pool;
 pub mod server;
+pub mod snapshot;
 pub mod state;
 pub mod store;
 pub mod thread_pool;
@@ -30,25 +40,27 @@ pub mod transaction_output;
 
 use self::{
     address::Address,
+    balance_stats::BalanceStats,
     batcher::Batcher,
     block::{iterate_transactions, Block, BlockHeader, BlockTrait},
     block_file_reader::BlockFileReader,
     buffer_writer::BufferWriter,
     chronometer::Chronometer,
+    chunked_vec::ChunkedVec,
     client::Client,
     configuration::Configuration,
     create_server::create_server,
     error::Error,
     executor::Executor,
-    hashing_buffer_reader::{Hasher, HashingBufferReader},
-    hashmap::{AddressHashMap, TransactionOutputHashMap},
+    hashing_buffer_reader::{Hasher, HashingBufferReader, ParseError, ParseErrorKind, ParseResult, ParseResultExt},
+    hashmap::{RandomShardedHashMap, ShardedAddressHashMap, ShardedTransactionOutputHashMap, TransactionOutputHashMap},
     last_blocks::LastBlocks,
     logger::{Logger, PartialLogger},
     sequential_thread_pool::SequentialThreadPool,
     server::Server,
     state::State,
     store::{
-        AugmentedStore, AugmentedTransactionStore, IndexedStore, IndexedTransactionStoreBackend, Store, TransactionStoreAugmentation,
+        AugmentedTransactionStore, IndexOptions, IndexedStore, IndexedTransactionStoreBackend, TransactionStoreAugmentation,
         TransactionStoreBackendTrait,
     },
     thread_pool::ThreadPool,
@@ -69,16 +81,39 @@ use tokio::{
 };
 
 fn main() {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    match arguments.first().map(String::as_str) {
+        None => run(),
+        Some("benchmark-store") => commands::benchmark_store(&arguments[1..]),
+        Some("check") => commands::check(&arguments[1..]),
+        Some("export") => commands::export(&arguments[1..]),
+        Some("utxo-set-hash") => commands::utxo_set_hash(&arguments[1..]),
+        Some(command) => panic!("Unknown command {}", command),
+    }
+}
+
+fn run() {
     let configuration = Configuration::new();
 
     let logger = Logger::new();
-    let store = match IndexedStore::from_file(configuration.store_file_path(), &logger) {
+    let store = match IndexedStore::from_file(
+        configuration.store_file_path(),
+        configuration.threads(),
+        configuration.index_options(),
+        &logger,
+    ) {
         Some(store) => store,
         None => {
             let reader = BlockFileReader::new(configuration.block_files_path());
             let mut blocks = reader.blocks(configuration.threads(), &logger);
             blocks.truncate(blocks.len() - configuration.confirmations() + 1);
-            let store = IndexedStore::from_blocks(blocks, configuration.threads(), configuration.batch_size(), logger);
+            let threads = configuration.threads();
+            let batch_size = configuration.batch_size();
+            let options = configuration.index_options();
+            let store = match configuration.snapshot_file_path() {
+                Some(path) => IndexedStore::from_snapshot(path, blocks, threads, batch_size, options, logger),
+                None => IndexedStore::from_blocks(blocks, threads, batch_size, options, logger),
+            };
             store.to_file(configuration.store_file_path(), &logger);
             store
         }
@@ -95,8 +130,14 @@ fn main() {
 
     let mut executor = Executor::new();
 
-    executor.spawn_runtime(state.clone(), |state| async move {
-        state.update().await;
+    executor.spawn_runtime(state.clone(), {
+        let configuration = configuration.clone();
+        |state| async move {
+            if state.add_block_times().await {
+                state.write(configuration.store_file_path()).await;
+            }
+            state.update().await;
+        }
     });
 
     executor.join();
@@ -104,6 +145,7 @@ fn main() {
     let mut executor = Executor::new();
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(256);
+    let (balance_stats_tx, mut balance_stats_rx) = tokio::sync::mpsc::channel(256);
 
     executor.spawn_runtime(state.clone(), {
         let configuration = configuration.clone();
@@ -112,6 +154,7 @@ fn main() {
                 sleep(Duration::from_millis(configuration.update_interval())).await;
                 if state.update().await {
                     tx.send(()).await.unwrap();
+                    balance_stats_tx.send(()).await.unwrap();
                 }
             }
         }
@@ -127,6 +170,13 @@ fn main() {
         }
     });
 
+    executor.spawn_runtime(state.clone(), |state| async move {
+        loop {
+            state.update_balance_stats().await;
+            balance_stats_rx.recv().await;
+        }
+    });
+
     executor.spawn_runtime(state.clone(), {
         let configuration = configuration.clone();
         |state| async move {
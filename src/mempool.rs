@@ -0,0 +1,365 @@
+use crate::{
+    hashmap::ShardedHashMap, store::Coin, Address, Arc, HashMap, HashSet, ShardedTransactionOutputHashMap, SystemTime, Transaction,
+    TransactionOutput,
+};
+
+/// Number of replaced transactions kept to explain why their outputs left the mempool.
+pub const MAX_REPLACEMENTS: usize = 10_000;
+
+/// Shards of the maps holding the mempool transactions, so that updating a copy of them only duplicates the shards of
+/// the transactions that changed.
+pub const MEMPOOL_SHARDS: usize = 1 << 12;
+
+/// Transaction of the mempool, with the time it was first fetched in seconds since the epoch.
+#[derive(Debug)]
+pub struct MempoolTransaction {
+    pub transaction: Transaction,
+    pub first_seen: u64,
+}
+
+impl MempoolTransaction {
+    pub fn new(transaction: Transaction) -> Self {
+        let first_seen = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        Self { transaction, first_seen }
+    }
+}
+
+/// Mempool transaction with the coins it spends, in the order of its inputs.
+#[derive(Debug, Clone)]
+pub struct MempoolEntry {
+    pub transaction: Arc<MempoolTransaction>,
+    pub spent_coins: Vec<Coin>,
+    /// Whether the transaction signals replaceability or spends a mempool transaction that does, following BIP125.
+    pub replaceable: bool,
+}
+
+/// Transaction that left the mempool because a transaction spending some of the same outputs, or spending outputs of a
+/// transaction replaced by it, took its place.
+#[derive(Debug)]
+pub struct Replacement {
+    pub hash: [u8; 32],
+    pub replaced_by: [u8; 32],
+    /// Outputs of the replaced transaction that have an address, with the address and the value.
+    pub outputs: Vec<(TransactionOutput, Address, u64)>,
+}
+
+impl Replacement {
+    fn new(transaction: &Transaction, replaced_by: [u8; 32]) -> Self {
+        let outputs = transaction
+            .outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, output)| {
+                let address = Address::from_script(&output.script).ok()?;
+                Some((
+                    TransactionOutput::new(transaction.hash, index.try_into().unwrap()),
+                    address,
+                    output.value,
+                ))
+            })
+            .collect();
+        Self {
+            hash: transaction.hash,
+            replaced_by,
+            outputs,
+        }
+    }
+}
+
+/// Replacements of the transactions that left the mempool, given with their parents first, by the transactions that entered
+/// it. A transaction is replaced by a transaction spending one of the same outputs, and the transactions spending its outputs
+/// are replaced along with it.
+pub fn find_replacements(removed: &[&Transaction], added: &[&Transaction]) -> Vec<Replacement> {
+    let mut spenders = HashMap::new();
+    for transaction in added {
+        for input in &transaction.inputs {
+            spenders.insert(&input.txo, transaction.hash);
+        }
+    }
+    let mut replaced_by = HashMap::new();
+    let mut replacements = Vec::new();
+    for transaction in removed {
+        let replacement = transaction
+            .inputs
+            .iter()
+            .find_map(|input| spenders.get(&input.txo).or_else(|| replaced_by.get(&input.txo.hash)).copied());
+        if let Some(replacement) = replacement {
+            replaced_by.insert(transaction.hash, replacement);
+            replacements.push(Replacement::new(transaction, replacement));
+        }
+    }
+    replacements
+}
+
+/// Mempool transactions that spend outputs of the store, of the tail blocks or of other mempool transactions, as of the
+/// last update.
+#[derive(Debug, Clone)]
+pub struct MempoolSnapshot {
+    entries: ShardedHashMap<[u8; 32], MempoolEntry>,
+    /// Transaction spending each output spent by the transactions.
+    spenders: ShardedTransactionOutputHashMap<[u8; 32]>,
+    vsize: usize,
+    /// Transactions left out because they spend outputs that are not known.
+    orphans: Vec<Arc<MempoolTransaction>>,
+    /// Last replaced transactions, oldest first.
+    replacements: Vec<Arc<Replacement>>,
+}
+
+impl Default for MempoolSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MempoolSnapshot {
+    pub fn new() -> Self {
+        Self {
+            entries: ShardedHashMap::with_shards(MEMPOOL_SHARDS),
+            spenders: ShardedTransactionOutputHashMap::with_shards(MEMPOOL_SHARDS),
+            vsize: 0,
+            orphans: Vec::new(),
+            replacements: Vec::new(),
+        }
+    }
+
+    /// Adds a transaction after the mempool transactions it spends.
+    pub fn add(&mut self, transaction: Arc<MempoolTransaction>, spent_coins: Vec<Coin>) {
+        self.vsize += transaction.transaction.vsize();
+        let hash = transaction.transaction.hash;
+        let replaceable = transaction.transaction.signals_replaceability()
+            || transaction
+                .transaction
+                .inputs
+                .iter()
+                .any(|input| matches!(self.entries.get(&input.txo.hash), Some(entry) if entry.replaceable));
+        for input in &transaction.transaction.inputs {
+            self.spenders.insert(input.txo.clone(), hash);
+        }
+        self.entries.insert(
+            hash,
+            MempoolEntry {
+                transaction,
+                spent_coins,
+                replaceable,
+            },
+        );
+    }
+
+    /// Removes a transaction after the mempool transactions spending it.
+    pub fn remove(&mut self, hash: &[u8; 32]) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(hash)?;
+        self.vsize -= entry.transaction.transaction.vsize();
+        for input in &entry.transaction.transaction.inputs {
+            self.spenders.remove(&input.txo);
+        }
+        Some(entry)
+    }
+
+    pub fn transaction(&self, hash: &[u8; 32]) -> Option<&MempoolEntry> {
+        self.entries.get(hash)
+    }
+
+    /// Transactions among the given ones and the ones spending their outputs, directly or not, each one after the
+    /// transactions it spends among them.
+    pub fn with_descendants(&self, hashes: impl IntoIterator<Item = [u8; 32]>) -> Vec<[u8; 32]> {
+        let mut found = HashSet::new();
+        let mut pending: Vec<_> = hashes.into_iter().filter(|hash| self.entries.contains_key(hash)).collect();
+        while let Some(hash) = pending.pop() {
+            if !found.insert(hash) {
+                continue;
+            }
+            let outputs = self.entries.get(&hash).unwrap().transaction.transaction.outputs.len();
+            for index in 0..outputs {
+                let txo = TransactionOutput::new(hash, index.try_into().unwrap());
+                pending.extend(self.spenders.get(&txo));
+            }
+        }
+        let hashes: Vec<_> = found.iter().copied().collect();
+        sort_topologically(&hashes, |hash| {
+            let inputs = &self.entries.get(hash).unwrap().transaction.transaction.inputs;
+            inputs
+                .iter()
+                .map(|input| input.txo.hash)
+                .filter(|hash| found.contains(hash))
+                .collect()
+        })
+    }
+
+    /// Transactions that a confirmed transaction takes the place of, which are the transaction itself and the ones spending
+    /// the same outputs.
+    pub fn conflicts(&self, transaction: &Transaction) -> Vec<[u8; 32]> {
+        let spenders = transaction
+            .inputs
+            .iter()
+            .filter_map(|input| self.spenders.get(&input.txo))
+            .filter(|hash| **hash != transaction.hash);
+        self.entries
+            .get(&transaction.hash)
+            .map(|_| transaction.hash)
+            .into_iter()
+            .chain(spenders.copied())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total virtual size of the transactions.
+    pub fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    /// Number of mempool transactions left out because they spend outputs that are not known.
+    pub fn orphans(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn set_orphans(&mut self, orphans: Vec<Arc<MempoolTransaction>>) {
+        self.orphans = orphans;
+    }
+
+    pub fn take_orphans(&mut self) -> Vec<Arc<MempoolTransaction>> {
+        std::mem::take(&mut self.orphans)
+    }
+
+    /// Drops the orphans that left the mempool.
+    pub fn remove_orphans(&mut self, hashes: &HashSet<[u8; 32]>) {
+        self.orphans.retain(|orphan| !hashes.contains(&orphan.transaction.hash));
+    }
+
+    pub fn set_replacements(&mut self, replacements: Vec<Arc<Replacement>>) {
+        self.replacements = replacements;
+    }
+
+    /// Iterates over the outputs of the address from the last replaced transactions, newest first, with the hash of the
+    /// transaction that replaced them.
+    pub fn iterate_replaced_outputs(&self, address: &Address, mut callback: impl FnMut(&TransactionOutput, u64, &[u8; 32])) {
+        for replacement in self.replacements.iter().rev() {
+            for (txo, output_address, value) in &replacement.outputs {
+                if output_address == address {
+                    callback(txo, *value, &replacement.replaced_by);
+                }
+            }
+        }
+    }
+}
+
+/// Orders the transactions so that each one comes after the transactions it spends among them, keeping the given order
+/// otherwise. `parents` returns the hashes of the transactions spent by a transaction, which must all be part of `hashes`.
+pub fn sort_topologically(hashes: &[[u8; 32]], parents: impl Fn(&[u8; 32]) -> Vec<[u8; 32]>) -> Vec<[u8; 32]> {
+    let mut visited = HashSet::new();
+    let mut sorted = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+        if !visited.insert(*hash) {
+            continue;
+        }
+        // Depth first search with an explicit stack, as chains of unconfirmed transactions can be long.
+        let mut stack = vec![(*hash, parents(hash), 0)];
+        while let Some((hash, parents_of_hash, index)) = stack.last_mut() {
+            match parents_of_hash.get(*index) {
+                Some(parent) => {
+                    *index += 1;
+                    let parent = *parent;
+                    if visited.insert(parent) {
+                        stack.push((parent, parents(&parent), 0));
+                    }
+                }
+                None => {
+                    sorted.push(*hash);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MempoolSnapshot, MempoolTransaction};
+    use crate::{hex, Arc, HashMap, Transaction};
+
+    /// Transaction spending the outputs `(hash, index)` with a single P2PKH output.
+    fn transaction(inputs: &[([u8; 32], u8)], sequence: &str) -> Transaction {
+        let inputs: String = inputs
+            .iter()
+            .map(|(hash, index)| format!("{}{:02x}00000000{}", hex::encode(hash), index, sequence))
+            .collect();
+        let outputs = "01e8030000000000001976a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac";
+        let buffer = format!("02000000{:02x}{}{}00000000", inputs.len() / 82, inputs, outputs);
+        Transaction::from_slice(&hex::decode(buffer).unwrap()).unwrap()
+    }
+
+    fn sort(hashes: &[u8], parents: &[(u8, &[u8])]) -> Vec<u8> {
+        let parents: HashMap<_, _> = parents
+            .iter()
+            .map(|(hash, parents)| ([*hash; 32], parents.iter().map(|parent| [*parent; 32]).collect::<Vec<_>>()))
+            .collect();
+        let hashes: Vec<_> = hashes.iter().map(|hash| [*hash; 32]).collect();
+        super::sort_topologically(&hashes, |hash| parents.get(hash).cloned().unwrap_or_default())
+            .iter()
+            .map(|hash| hash[0])
+            .collect()
+    }
+
+    #[test]
+    fn sort_topologically() {
+        assert_eq!(sort(&[1, 2, 3], &[]), vec![1, 2, 3]);
+        assert_eq!(sort(&[3, 1, 2], &[(3, &[2]), (2, &[1])]), vec![1, 2, 3]);
+        assert_eq!(sort(&[4, 3, 1, 2], &[(4, &[2, 3]), (3, &[1])]), vec![2, 1, 3, 4]);
+        assert_eq!(sort(&[2, 1, 1], &[(2, &[1, 1])]), vec![1, 2]);
+    }
+
+    #[test]
+    fn find_replacements() {
+        let replaced = transaction(&[([1; 32], 0), ([1; 32], 1)], "fdffffff");
+        let child = transaction(&[(replaced.hash, 0)], "ffffffff");
+        let unrelated = transaction(&[([2; 32], 0)], "ffffffff");
+        let replacement = transaction(&[([1; 32], 1)], "fdffffff");
+        let replacements = super::find_replacements(&[&replaced, &child, &unrelated], &[&replacement]);
+        let replacements: Vec<_> = replacements
+            .iter()
+            .map(|replacement| (replacement.hash, replacement.replaced_by, replacement.outputs.len()))
+            .collect();
+        assert_eq!(
+            replacements,
+            vec![(replaced.hash, replacement.hash, 1), (child.hash, replacement.hash, 1)]
+        );
+    }
+
+    #[test]
+    fn mempool_snapshot() {
+        let parent = transaction(&[([1; 32], 0)], "ffffffff");
+        let child = transaction(&[(parent.hash, 0)], "ffffffff");
+        let grandchild = transaction(&[(child.hash, 0), ([2; 32], 0)], "ffffffff");
+        let unrelated = transaction(&[([3; 32], 0)], "ffffffff");
+        let conflicting = transaction(&[([2; 32], 0)], "fdffffff");
+        let mut mempool = MempoolSnapshot::new();
+        for transaction in [&parent, &child, &grandchild, &unrelated] {
+            mempool.add(Arc::new(MempoolTransaction::new(transaction.clone())), Vec::new());
+        }
+        assert_eq!(mempool.len(), 4);
+        let vsize = mempool.vsize();
+        assert_eq!(
+            mempool.with_descendants([grandchild.hash, parent.hash]),
+            vec![parent.hash, child.hash, grandchild.hash]
+        );
+        assert_eq!(mempool.with_descendants([child.hash, [4; 32]]), vec![child.hash, grandchild.hash]);
+        assert_eq!(mempool.conflicts(&parent), vec![parent.hash]);
+        assert_eq!(mempool.conflicts(&conflicting), vec![grandchild.hash]);
+        assert_eq!(
+            mempool.remove(&grandchild.hash).unwrap().transaction.transaction.hash,
+            grandchild.hash
+        );
+        assert!(mempool.remove(&grandchild.hash).is_none());
+        assert_eq!(mempool.len(), 3);
+        assert_eq!(mempool.vsize(), vsize - grandchild.vsize());
+        assert!(mempool.conflicts(&conflicting).is_empty());
+        assert_eq!(mempool.with_descendants([parent.hash]), vec![parent.hash, child.hash]);
+    }
+}
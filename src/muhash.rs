@@ -0,0 +1,239 @@
+use crate::{BufferWriter, HashingBufferReader, ParseResult, ParseResultExt, TryInto};
+use sha2::{Digest, Sha256};
+
+const LIMBS: usize = 48;
+const BYTES: usize = LIMBS * 8;
+// The modulus is 2^3072 - PRIME_DIFF.
+const PRIME_DIFF: u64 = 1103717;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Num3072 {
+    limbs: [u64; LIMBS],
+}
+
+impl Num3072 {
+    fn one() -> Self {
+        let mut limbs = [0u64; LIMBS];
+        limbs[0] = 1;
+        Self { limbs }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut limbs = [0u64; LIMBS];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap());
+        }
+        Self { limbs }
+    }
+
+    fn to_bytes(&self) -> [u8; BYTES] {
+        let mut bytes = [0u8; BYTES];
+        for (i, limb) in self.limbs.iter().enumerate() {
+            bytes[8 * i..8 * i + 8].copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn is_overflow(&self) -> bool {
+        self.limbs[0] > u64::MAX - PRIME_DIFF && self.limbs[1..].iter().all(|limb| *limb == u64::MAX)
+    }
+
+    fn full_reduce(&mut self) {
+        let mut carry = PRIME_DIFF;
+        for limb in self.limbs.iter_mut() {
+            let (value, overflow) = limb.overflowing_add(carry);
+            *limb = value;
+            carry = overflow as u64;
+        }
+    }
+
+    fn multiply(&self, other: &Self) -> Self {
+        let mut product = [0u64; 2 * LIMBS];
+        for i in 0..LIMBS {
+            let mut carry: u128 = 0;
+            for j in 0..LIMBS {
+                let value = product[i + j] as u128 + (self.limbs[i] as u128) * (other.limbs[j] as u128) + carry;
+                product[i + j] = value as u64;
+                carry = value >> 64;
+            }
+            product[i + LIMBS] = carry as u64;
+        }
+        let mut result = Self { limbs: [0u64; LIMBS] };
+        let mut carry: u128 = 0;
+        for i in 0..LIMBS {
+            let value = product[i] as u128 + (product[i + LIMBS] as u128) * (PRIME_DIFF as u128) + carry;
+            result.limbs[i] = value as u64;
+            carry = value >> 64;
+        }
+        while carry > 0 {
+            carry *= PRIME_DIFF as u128;
+            for limb in result.limbs.iter_mut() {
+                let value = *limb as u128 + carry;
+                *limb = value as u64;
+                carry = value >> 64;
+                if carry == 0 {
+                    break;
+                }
+            }
+        }
+        if result.is_overflow() {
+            result.full_reduce();
+        }
+        result
+    }
+
+    fn inverse(&self) -> Self {
+        // Fermat's little theorem: a^(p - 2) is the inverse of a modulo the prime p.
+        let mut exponent = [u64::MAX; LIMBS];
+        exponent[0] = u64::MAX - PRIME_DIFF - 1;
+        let mut result = Self::one();
+        for i in (0..LIMBS * 64).rev() {
+            result = result.multiply(&result);
+            if (exponent[i / 64] >> (i % 64)) & 1 == 1 {
+                result = result.multiply(self);
+            }
+        }
+        result
+    }
+}
+
+fn chacha20_quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn chacha20_keystream(key: &[u8; 32], output: &mut [u8]) {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        input[4 + i] = u32::from_le_bytes(key[4 * i..4 * i + 4].try_into().unwrap());
+    }
+    for (counter, block) in output.chunks_mut(64).enumerate() {
+        input[12] = counter.try_into().unwrap();
+        let mut state = input;
+        for _ in 0..10 {
+            chacha20_quarter_round(&mut state, 0, 4, 8, 12);
+            chacha20_quarter_round(&mut state, 1, 5, 9, 13);
+            chacha20_quarter_round(&mut state, 2, 6, 10, 14);
+            chacha20_quarter_round(&mut state, 3, 7, 11, 15);
+            chacha20_quarter_round(&mut state, 0, 5, 10, 15);
+            chacha20_quarter_round(&mut state, 1, 6, 11, 12);
+            chacha20_quarter_round(&mut state, 2, 7, 8, 13);
+            chacha20_quarter_round(&mut state, 3, 4, 9, 14);
+        }
+        for (i, bytes) in block.chunks_mut(4).enumerate() {
+            bytes.copy_from_slice(&state[i].wrapping_add(input[i]).to_le_bytes());
+        }
+    }
+}
+
+fn to_num3072(data: &[u8]) -> Num3072 {
+    let key: [u8; 32] = Sha256::digest(data).into();
+    let mut bytes = [0u8; BYTES];
+    chacha20_keystream(&key, &mut bytes);
+    Num3072::from_bytes(&bytes)
+}
+
+/// Bitcoin Core's MuHash3072 rolling set hash, as used by `gettxoutsetinfo muhash`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MuHash3072 {
+    numerator: Num3072,
+    denominator: Num3072,
+}
+
+impl Default for MuHash3072 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MuHash3072 {
+    pub fn new() -> Self {
+        Self {
+            numerator: Num3072::one(),
+            denominator: Num3072::one(),
+        }
+    }
+
+    pub fn insert(&mut self, data: &[u8]) {
+        self.numerator = self.numerator.multiply(&to_num3072(data));
+    }
+
+    pub fn remove(&mut self, data: &[u8]) {
+        self.denominator = self.denominator.multiply(&to_num3072(data));
+    }
+
+    pub fn finalize(&self) -> [u8; 32] {
+        let value = self.numerator.multiply(&self.denominator.inverse());
+        Sha256::digest(&value.to_bytes()).into()
+    }
+
+    pub fn from_reader(reader: &mut HashingBufferReader) -> ParseResult<Self> {
+        let numerator = Num3072::from_bytes(reader.read_buffer(BYTES, &mut None).field("muhash numerator")?);
+        let denominator = Num3072::from_bytes(reader.read_buffer(BYTES, &mut None).field("muhash denominator")?);
+        Ok(Self { numerator, denominator })
+    }
+
+    pub fn to_writer(&self, writer: &mut BufferWriter) {
+        writer.write_buffer(&self.numerator.to_bytes());
+        writer.write_buffer(&self.denominator.to_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MuHash3072;
+    use crate::{reverse_hex, BufferWriter, HashingBufferReader};
+
+    fn element(value: u8) -> [u8; 32] {
+        let mut data = [0u8; 32];
+        data[0] = value;
+        data
+    }
+
+    #[test]
+    fn finalize() {
+        assert_eq!(
+            reverse_hex::encode(MuHash3072::new().finalize()),
+            "dd5ad2a105c2d29495f577245c357409002329b9f4d6182c0af3dc2f462555c8"
+        );
+        let mut muhash = MuHash3072::new();
+        muhash.insert(&element(0));
+        muhash.insert(&element(1));
+        muhash.remove(&element(2));
+        assert_eq!(
+            reverse_hex::encode(muhash.finalize()),
+            "10d312b100cbd32ada024a6646e40d3482fcff103668d2625f10002a607d5863"
+        );
+    }
+
+    #[test]
+    fn remove() {
+        let mut muhash = MuHash3072::new();
+        for value in 0..10 {
+            muhash.insert(&element(value));
+        }
+        for value in 0..10 {
+            muhash.remove(&element(value));
+        }
+        assert_eq!(muhash.finalize(), MuHash3072::new().finalize());
+    }
+
+    #[test]
+    fn to_writer() {
+        let mut muhash = MuHash3072::new();
+        muhash.insert(&element(3));
+        muhash.remove(&element(4));
+        let mut writer = BufferWriter::new();
+        muhash.to_writer(&mut writer);
+        let buffer = writer.buffer();
+        assert_eq!(buffer.len(), 768);
+        assert_eq!(MuHash3072::from_reader(&mut HashingBufferReader::new(&buffer)), Ok(muhash));
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{hex, HashingBufferReader, ParseResult, ParseResultExt};
+
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4C;
+pub const OP_PUSHDATA2: u8 = 0x4D;
+pub const OP_PUSHDATA4: u8 = 0x4E;
+pub const OP_1NEGATE: u8 = 0x4F;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_RETURN: u8 = 0x6A;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_HASH160: u8 = 0xA9;
+pub const OP_CHECKSIG: u8 = 0xAC;
+pub const OP_CHECKMULTISIG: u8 = 0xAE;
+
+/// Maximum number of public keys of a multisig script, like Bitcoin Core's `MAX_PUBKEYS_PER_MULTISIG`.
+const MAX_MULTISIG_KEYS: u8 = 20;
+
+/// Names of the opcodes from OP_NOP to OP_CHECKSIGADD, as written by Bitcoin Core.
+const OPCODE_NAMES: [&str; 90] = [
+    "OP_NOP",
+    "OP_VER",
+    "OP_IF",
+    "OP_NOTIF",
+    "OP_VERIF",
+    "OP_VERNOTIF",
+    "OP_ELSE",
+    "OP_ENDIF",
+    "OP_VERIFY",
+    "OP_RETURN",
+    "OP_TOALTSTACK",
+    "OP_FROMALTSTACK",
+    "OP_2DROP",
+    "OP_2DUP",
+    "OP_3DUP",
+    "OP_2OVER",
+    "OP_2ROT",
+    "OP_2SWAP",
+    "OP_IFDUP",
+    "OP_DEPTH",
+    "OP_DROP",
+    "OP_DUP",
+    "OP_NIP",
+    "OP_OVER",
+    "OP_PICK",
+    "OP_ROLL",
+    "OP_ROT",
+    "OP_SWAP",
+    "OP_TUCK",
+    "OP_CAT",
+    "OP_SUBSTR",
+    "OP_LEFT",
+    "OP_RIGHT",
+    "OP_SIZE",
+    "OP_INVERT",
+    "OP_AND",
+    "OP_OR",
+    "OP_XOR",
+    "OP_EQUAL",
+    "OP_EQUALVERIFY",
+    "OP_RESERVED1",
+    "OP_RESERVED2",
+    "OP_1ADD",
+    "OP_1SUB",
+    "OP_2MUL",
+    "OP_2DIV",
+    "OP_NEGATE",
+    "OP_ABS",
+    "OP_NOT",
+    "OP_0NOTEQUAL",
+    "OP_ADD",
+    "OP_SUB",
+    "OP_MUL",
+    "OP_DIV",
+    "OP_MOD",
+    "OP_LSHIFT",
+    "OP_RSHIFT",
+    "OP_BOOLAND",
+    "OP_BOOLOR",
+    "OP_NUMEQUAL",
+    "OP_NUMEQUALVERIFY",
+    "OP_NUMNOTEQUAL",
+    "OP_LESSTHAN",
+    "OP_GREATERTHAN",
+    "OP_LESSTHANOREQUAL",
+    "OP_GREATERTHANOREQUAL",
+    "OP_MIN",
+    "OP_MAX",
+    "OP_WITHIN",
+    "OP_RIPEMD160",
+    "OP_SHA1",
+    "OP_SHA256",
+    "OP_HASH160",
+    "OP_HASH256",
+    "OP_CODESEPARATOR",
+    "OP_CHECKSIG",
+    "OP_CHECKSIGVERIFY",
+    "OP_CHECKMULTISIG",
+    "OP_CHECKMULTISIGVERIFY",
+    "OP_NOP1",
+    "OP_CHECKLOCKTIMEVERIFY",
+    "OP_CHECKSEQUENCEVERIFY",
+    "OP_NOP4",
+    "OP_NOP5",
+    "OP_NOP6",
+    "OP_NOP7",
+    "OP_NOP8",
+    "OP_NOP9",
+    "OP_NOP10",
+    "OP_CHECKSIGADD",
+];
+
+/// Name of an opcode that does not push data, with small integers written as numbers like Bitcoin Core does.
+pub fn opcode_name(opcode: u8) -> String {
+    match opcode {
+        OP_1NEGATE => "-1".to_string(),
+        0x50 => "OP_RESERVED".to_string(),
+        OP_1..=OP_16 => (opcode - OP_1 + 1).to_string(),
+        0x61..=0xBA => OPCODE_NAMES[usize::from(opcode - 0x61)].to_string(),
+        0xFF => "OP_INVALIDOPCODE".to_string(),
+        _ => "OP_UNKNOWN".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction<'a> {
+    /// Data pushed by OP_0, a direct push or one of the OP_PUSHDATA opcodes, given first.
+    Push(u8, &'a [u8]),
+    Opcode(u8),
+}
+
+/// Instructions of a script, ending with an error when a push goes past the end of the script.
+pub struct Instructions<'a> {
+    reader: HashingBufferReader<'a>,
+    failed: bool,
+}
+
+impl<'a> Instructions<'a> {
+    pub fn new(script: &'a [u8]) -> Self {
+        Self {
+            reader: HashingBufferReader::new(script),
+            failed: false,
+        }
+    }
+
+    fn read_instruction(&mut self) -> ParseResult<Instruction<'a>> {
+        let opcode = self.reader.read_u8(&mut None).field("opcode")?;
+        let length = match opcode {
+            OP_0..=0x4B => opcode.into(),
+            OP_PUSHDATA1 => self.reader.read_u8(&mut None).field("push length")?.into(),
+            OP_PUSHDATA2 => self.reader.read_u16_le(&mut None).field("push length")?.into(),
+            OP_PUSHDATA4 => self.reader.read_u32_le(&mut None).field("push length")?.try_into().unwrap(),
+            opcode => return Ok(Instruction::Opcode(opcode)),
+        };
+        let data = self.reader.read_buffer(length, &mut None).field("push data")?;
+        Ok(Instruction::Push(opcode, data))
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = ParseResult<Instruction<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_at_end() {
+            return None;
+        }
+        let instruction = self.read_instruction();
+        self.failed = instruction.is_err();
+        Some(instruction)
+    }
+}
+
+pub fn is_op_return(script: &[u8]) -> bool {
+    script.first() == Some(&OP_RETURN)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    P2PK,
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    /// Bare multisig, with the number of signatures required and the number of keys.
+    Multisig(u8, u8),
+    /// OP_RETURN followed by push opcodes only, which like Bitcoin Core's `IsPushOnly` include OP_1NEGATE to OP_16.
+    OpReturn,
+    NonStandard,
+}
+
+impl ScriptType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptType::P2PK => "p2pk",
+            ScriptType::P2PKH => "p2pkh",
+            ScriptType::P2SH => "p2sh",
+            ScriptType::P2WPKH => "p2wpkh",
+            ScriptType::P2WSH => "p2wsh",
+            ScriptType::P2TR => "p2tr",
+            ScriptType::Multisig(..) => "multisig",
+            ScriptType::OpReturn => "op_return",
+            ScriptType::NonStandard => "nonstandard",
+        }
+    }
+}
+
+fn is_public_key(data: &[u8]) -> bool {
+    matches!((data.len(), data.first()), (33, Some(0x02 | 0x03)) | (65, Some(0x04)))
+}
+
+fn small_integer(instruction: &ParseResult<Instruction>) -> Option<u8> {
+    match instruction {
+        Ok(Instruction::Opcode(opcode @ OP_1..=OP_16)) => Some(opcode - OP_1 + 1),
+        _ => None,
+    }
+}
+
+fn is_push(instruction: &ParseResult<Instruction>) -> bool {
+    matches!(instruction, Ok(Instruction::Push(..) | Instruction::Opcode(OP_1NEGATE..=OP_16)))
+}
+
+fn classify_multisig(script: &[u8]) -> Option<ScriptType> {
+    let instructions: Vec<_> = Instructions::new(script).collect();
+    let (first, rest) = instructions.split_first()?;
+    let (last, rest) = rest.split_last()?;
+    let (keys, keys_data) = rest.split_last()?;
+    let required = small_integer(first)?;
+    let keys = small_integer(keys)?;
+    let is_multisig = *last == Ok(Instruction::Opcode(OP_CHECKMULTISIG))
+        && required <= keys
+        && keys <= MAX_MULTISIG_KEYS
+        && keys_data.len() == usize::from(keys)
+        && keys_data
+            .iter()
+            .all(|instruction| matches!(instruction, Ok(Instruction::Push(_, data)) if is_public_key(data)));
+    is_multisig.then_some(ScriptType::Multisig(required, keys))
+}
+
+/// Standard template matched by an output script.
+pub fn classify(script: &[u8]) -> ScriptType {
+    match script {
+        [OP_DUP, OP_HASH160, 0x14, .., OP_EQUALVERIFY, OP_CHECKSIG] if script.len() == 25 => ScriptType::P2PKH,
+        [OP_HASH160, 0x14, .., OP_EQUAL] if script.len() == 23 => ScriptType::P2SH,
+        [OP_0, 0x14, ..] if script.len() == 22 => ScriptType::P2WPKH,
+        [OP_0, 0x20, ..] if script.len() == 34 => ScriptType::P2WSH,
+        [OP_1, 0x20, ..] if script.len() == 34 => ScriptType::P2TR,
+        [length @ (0x21 | 0x41), .., OP_CHECKSIG]
+            if script.len() == usize::from(*length) + 2 && is_public_key(&script[1..script.len() - 1]) =>
+        {
+            ScriptType::P2PK
+        }
+        [OP_RETURN, ..] if Instructions::new(&script[1..]).all(|instruction| is_push(&instruction)) => ScriptType::OpReturn,
+        _ => classify_multisig(script).unwrap_or(ScriptType::NonStandard),
+    }
+}
+
+/// Value of a pushed number, little endian with the sign in the highest bit.
+fn script_number(data: &[u8]) -> i64 {
+    let mut value: i64 = 0;
+    for (index, byte) in data.iter().enumerate() {
+        value |= i64::from(*byte) << (8 * index);
+    }
+    match data.last() {
+        Some(byte) if byte & 0x80 != 0 => -(value & !(0x80 << (8 * (data.len() - 1)))),
+        _ => value,
+    }
+}
+
+/// Script written like Bitcoin Core's `asm` field: pushes of up to 4 bytes as numbers, longer pushes in hex, other
+/// opcodes by name, and `[error]` where a push goes past the end of the script.
+pub fn to_asm(script: &[u8]) -> String {
+    let tokens: Vec<_> = Instructions::new(script)
+        .map(|instruction| match instruction {
+            Ok(Instruction::Push(_, data)) if data.len() <= 4 => script_number(data).to_string(),
+            Ok(Instruction::Push(_, data)) => hex::encode(data),
+            Ok(Instruction::Opcode(opcode)) => opcode_name(opcode),
+            Err(_) => "[error]".to_string(),
+        })
+        .collect();
+    tokens.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, Instruction, Instructions, ScriptType};
+    use crate::hex;
+
+    const KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn classify_hex(script: &str) -> ScriptType {
+        classify(&hex::decode(script).unwrap())
+    }
+
+    fn asm(script: &str) -> String {
+        super::to_asm(&hex::decode(script).unwrap())
+    }
+
+    #[test]
+    fn instructions() {
+        let script = hex::decode("004c02aabb4d0100cc51ae").unwrap();
+        let instructions: Vec<_> = Instructions::new(&script).map(Result::unwrap).collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::Push(0x00, &[]),
+                Instruction::Push(0x4C, &[0xAA, 0xBB]),
+                Instruction::Push(0x4D, &[0xCC]),
+                Instruction::Opcode(0x51),
+                Instruction::Opcode(0xAE),
+            ]
+        );
+        let script = hex::decode("5103aabb").unwrap();
+        let instructions: Vec<_> = Instructions::new(&script).collect();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[1].as_ref().unwrap_err().field, "push data");
+    }
+
+    #[test]
+    fn classify_templates() {
+        assert_eq!(
+            classify_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"),
+            ScriptType::P2PKH
+        );
+        assert_eq!(classify_hex("a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1887"), ScriptType::P2SH);
+        assert_eq!(classify_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6"), ScriptType::P2WPKH);
+        assert_eq!(classify_hex(&format!("0020{}", "11".repeat(32))), ScriptType::P2WSH);
+        assert_eq!(classify_hex(&format!("5120{}", "11".repeat(32))), ScriptType::P2TR);
+        assert_eq!(classify_hex(&format!("21{}ac", KEY)), ScriptType::P2PK);
+        assert_eq!(classify_hex(&format!("4104{}ac", "11".repeat(64))), ScriptType::P2PK);
+        assert_eq!(classify_hex(&format!("5121{}21{}52ae", KEY, KEY)), ScriptType::Multisig(1, 2));
+        assert_eq!(classify_hex(&format!("5321{}21{}52ae", KEY, KEY)), ScriptType::NonStandard);
+        assert_eq!(classify_hex("6a0b68656c6c6f20776f726c64"), ScriptType::OpReturn);
+        assert_eq!(classify_hex("6a"), ScriptType::OpReturn);
+        assert_eq!(classify_hex("6a76"), ScriptType::NonStandard);
+        assert_eq!(classify_hex("6a51"), ScriptType::OpReturn);
+        assert_eq!(classify_hex("6a4f6004aabbccdd"), ScriptType::OpReturn);
+        assert_eq!(classify_hex("6a0161"), ScriptType::OpReturn);
+        assert_eq!(classify_hex("6a61"), ScriptType::NonStandard);
+        assert_eq!(classify_hex("6a0b68656c6c6f"), ScriptType::NonStandard);
+        assert_eq!(classify_hex(""), ScriptType::NonStandard);
+    }
+
+    #[test]
+    fn to_asm() {
+        assert_eq!(
+            asm("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"),
+            "OP_DUP OP_HASH160 62e907b15cbf27d5425399ebf6f0fb50ebb88f18 OP_EQUALVERIFY OP_CHECKSIG"
+        );
+        assert_eq!(asm(&format!("5121{}51ae", KEY)), format!("1 {} 1 OP_CHECKMULTISIG", KEY));
+        assert_eq!(asm("6a04ffffffff"), "OP_RETURN -2147483647");
+        assert_eq!(asm("0002e8034f00b1ff"), "0 1000 -1 0 OP_CHECKLOCKTIMEVERIFY OP_INVALIDOPCODE");
+        assert_eq!(asm("6a0b68656c6c6f"), "OP_RETURN [error]");
+        assert_eq!(asm("bb50"), "OP_UNKNOWN OP_RESERVED");
+    }
+}
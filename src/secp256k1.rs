@@ -0,0 +1,179 @@
+use crate::TryInto;
+
+type FieldElement = [u64; 4];
+
+const P: FieldElement = [0xFFFFFFFEFFFFFC2F, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF];
+const P_PLUS_ONE_DIV_FOUR: FieldElement = [0xFFFFFFFFBFFFFF0C, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x3FFFFFFFFFFFFFFF];
+const C: u64 = 0x1000003D1;
+
+fn from_be_bytes(bytes: &[u8]) -> FieldElement {
+    let mut element = [0u64; 4];
+    for (i, limb) in element.iter_mut().enumerate() {
+        let offset = 24 - 8 * i;
+        *limb = u64::from_be_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    }
+    element
+}
+
+fn to_be_bytes(element: &FieldElement) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    for (i, limb) in element.iter().enumerate() {
+        let offset = 24 - 8 * i;
+        bytes[offset..offset + 8].copy_from_slice(&limb.to_be_bytes());
+    }
+    bytes
+}
+
+fn greater_or_equal(a: &FieldElement, b: &FieldElement) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] > b[i];
+        }
+    }
+    true
+}
+
+fn subtract(a: &FieldElement, b: &FieldElement) -> FieldElement {
+    let mut result = [0u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (value, borrow1) = a[i].overflowing_sub(b[i]);
+        let (value, borrow2) = value.overflowing_sub(borrow as u64);
+        result[i] = value;
+        borrow = borrow1 || borrow2;
+    }
+    result
+}
+
+fn add_small(a: &FieldElement, b: u64) -> FieldElement {
+    let mut result = *a;
+    let mut carry = b;
+    for limb in result.iter_mut() {
+        let (value, overflow) = limb.overflowing_add(carry);
+        *limb = value;
+        carry = overflow as u64;
+    }
+    result
+}
+
+fn reduce(product: &[u64; 8]) -> FieldElement {
+    let mut folded = [0u64; 4];
+    let mut carry: u128 = 0;
+    for i in 0..4 {
+        let value = product[i] as u128 + (product[i + 4] as u128) * (C as u128) + carry;
+        folded[i] = value as u64;
+        carry = value >> 64;
+    }
+    let mut result = [0u64; 4];
+    let mut carry = carry * (C as u128);
+    for i in 0..4 {
+        let value = folded[i] as u128 + carry;
+        result[i] = value as u64;
+        carry = value >> 64;
+    }
+    if carry > 0 {
+        result = add_small(&result, C);
+    }
+    if greater_or_equal(&result, &P) {
+        result = subtract(&result, &P);
+    }
+    result
+}
+
+fn multiply(a: &FieldElement, b: &FieldElement) -> FieldElement {
+    let mut product = [0u64; 8];
+    for i in 0..4 {
+        let mut carry: u128 = 0;
+        for j in 0..4 {
+            let value = product[i + j] as u128 + (a[i] as u128) * (b[j] as u128) + carry;
+            product[i + j] = value as u64;
+            carry = value >> 64;
+        }
+        product[i + 4] = carry as u64;
+    }
+    reduce(&product)
+}
+
+fn power(base: &FieldElement, exponent: &FieldElement) -> FieldElement {
+    let mut result = [1, 0, 0, 0];
+    for i in (0..256).rev() {
+        result = multiply(&result, &result);
+        if (exponent[i / 64] >> (i % 64)) & 1 == 1 {
+            result = multiply(&result, base);
+        }
+    }
+    result
+}
+
+pub fn decompress_public_key(public_key: &[u8; 33]) -> Option<[u8; 65]> {
+    let odd = match public_key[0] {
+        0x02 => false,
+        0x03 => true,
+        _ => return None,
+    };
+    let x = from_be_bytes(&public_key[1..]);
+    if greater_or_equal(&x, &P) {
+        return None;
+    }
+    let y_squared = add_small(&multiply(&multiply(&x, &x), &x), 7);
+    let y_squared = if greater_or_equal(&y_squared, &P) {
+        subtract(&y_squared, &P)
+    } else {
+        y_squared
+    };
+    let mut y = power(&y_squared, &P_PLUS_ONE_DIV_FOUR);
+    if multiply(&y, &y) != y_squared {
+        return None;
+    }
+    if (y[0] & 1 == 1) != odd {
+        y = subtract(&P, &y);
+    }
+    let mut result = [0u8; 65];
+    result[0] = 0x04;
+    result[1..33].copy_from_slice(&public_key[1..]);
+    result[33..].copy_from_slice(&to_be_bytes(&y));
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{hex, TryInto};
+
+    fn decompress(public_key: &str) -> Option<String> {
+        let public_key = hex::decode(public_key).unwrap();
+        super::decompress_public_key(&public_key.try_into().unwrap()).map(hex::encode)
+    }
+
+    #[test]
+    fn decompress_public_key() {
+        assert_eq!(
+            decompress("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+            Some(String::from(
+                "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+                 483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"
+            ))
+        );
+        assert_eq!(
+            decompress("03c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"),
+            Some(String::from(
+                "04c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5\
+                 e51e970159c23cc65c3a7be6b99315110809cd9acd992f1edc9bce55af301705"
+            ))
+        );
+        assert_eq!(
+            decompress("0311db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5c"),
+            Some(String::from(
+                "0411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5c\
+                 b2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3"
+            ))
+        );
+        assert_eq!(
+            decompress("020000000000000000000000000000000000000000000000000000000000000005"),
+            None
+        );
+        assert_eq!(
+            decompress("0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+            None
+        );
+    }
+}
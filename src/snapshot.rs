@@ -0,0 +1,185 @@
+use crate::{
+    compression::{
+        compress_amount, compress_script, decompress_amount, decompress_script, special_script_size, MAX_SCRIPT_SIZE, SPECIAL_SCRIPTS,
+    },
+    transaction::Output,
+    BufferWriter, TransactionOutput, TryInto,
+};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+};
+
+const SNAPSHOT_MAGIC: [u8; 5] = *b"utxo\xff";
+const SNAPSHOT_VERSION: u16 = 2;
+const NETWORK_MAGIC: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9];
+const COINS_COUNT_OFFSET: u64 = 43;
+
+#[derive(Debug, Clone)]
+pub struct SnapshotCoin {
+    pub height: u32,
+    pub coinbase: bool,
+    pub output: Output,
+}
+
+pub struct SnapshotReader {
+    reader: BufReader<File>,
+    base_block_hash: [u8; 32],
+    coins_count: u64,
+}
+
+impl SnapshotReader {
+    pub fn new(path: &str) -> Self {
+        let file = File::open(path).unwrap_or_else(|error| panic!("Could not open snapshot file {}: {}", path, error));
+        let mut reader = Self {
+            reader: BufReader::with_capacity(1 << 20, file),
+            base_block_hash: [0; 32],
+            coins_count: 0,
+        };
+        assert!(reader.read_array() == SNAPSHOT_MAGIC, "Invalid snapshot file {}", path);
+        let version = u16::from_le_bytes(reader.read_array());
+        assert!(version == SNAPSHOT_VERSION, "Unsupported snapshot version {}", version);
+        assert!(
+            reader.read_array() == NETWORK_MAGIC,
+            "Snapshot {} is not for the main network",
+            path
+        );
+        reader.base_block_hash = reader.read_array();
+        reader.coins_count = u64::from_le_bytes(reader.read_array());
+        reader
+    }
+
+    pub fn base_block_hash(&self) -> [u8; 32] {
+        self.base_block_hash
+    }
+
+    pub fn coins_count(&self) -> u64 {
+        self.coins_count
+    }
+
+    fn read_array<const N: usize>(&mut self) -> [u8; N] {
+        let mut buffer = [0u8; N];
+        self.reader.read_exact(&mut buffer).unwrap();
+        buffer
+    }
+
+    fn read_vec(&mut self, length: usize) -> Vec<u8> {
+        let mut buffer = vec![0u8; length];
+        self.reader.read_exact(&mut buffer).unwrap();
+        buffer
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        self.read_array::<1>()[0]
+    }
+
+    fn read_var_int_le(&mut self) -> u64 {
+        match self.read_u8() {
+            0xFD => u16::from_le_bytes(self.read_array()).into(),
+            0xFE => u32::from_le_bytes(self.read_array()).into(),
+            0xFF => u64::from_le_bytes(self.read_array()),
+            byte => byte.into(),
+        }
+    }
+
+    fn read_b128_var_int(&mut self) -> u64 {
+        let mut value = 0;
+        loop {
+            let byte = self.read_u8();
+            value = (value << 7) | u64::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            value += 1;
+        }
+    }
+
+    fn read_script(&mut self) -> Vec<u8> {
+        let kind = self.read_b128_var_int();
+        if kind < SPECIAL_SCRIPTS {
+            let payload = self.read_vec(special_script_size(kind));
+            decompress_script(kind, &payload)
+        } else {
+            let length = kind - SPECIAL_SCRIPTS;
+            if length > MAX_SCRIPT_SIZE {
+                io::copy(&mut (&mut self.reader).take(length), &mut io::sink()).unwrap();
+                vec![0x6a]
+            } else {
+                self.read_vec(length.try_into().unwrap())
+            }
+        }
+    }
+
+    fn read_coin(&mut self) -> SnapshotCoin {
+        let code = self.read_b128_var_int();
+        let value = decompress_amount(self.read_b128_var_int());
+        let script = self.read_script();
+        SnapshotCoin {
+            height: (code >> 1).try_into().unwrap(),
+            coinbase: code & 1 == 1,
+            output: Output { value, script },
+        }
+    }
+
+    pub fn coins(mut self, mut callback: impl FnMut(TransactionOutput, SnapshotCoin)) {
+        let mut count = 0;
+        while count < self.coins_count {
+            let hash = self.read_array();
+            let coins = self.read_var_int_le();
+            for _ in 0..coins {
+                let index = self.read_var_int_le().try_into().unwrap();
+                let coin = self.read_coin();
+                callback(TransactionOutput::new(hash, index), coin);
+            }
+            count += coins;
+        }
+        assert!(count == self.coins_count, "Snapshot contains more coins than announced");
+        assert!(self.reader.read(&mut [0u8; 1]).unwrap() == 0, "Snapshot contains trailing data");
+    }
+}
+
+pub struct SnapshotWriter<W: Write + Seek> {
+    writer: W,
+    coins_count: u64,
+}
+
+impl<W: Write + Seek> SnapshotWriter<W> {
+    pub fn new(writer: W, base_block_hash: [u8; 32]) -> Self {
+        let mut buffer = BufferWriter::new();
+        buffer.write_buffer(&SNAPSHOT_MAGIC);
+        buffer.write_buffer(&SNAPSHOT_VERSION.to_le_bytes());
+        buffer.write_buffer(&NETWORK_MAGIC);
+        buffer.write_hash(base_block_hash);
+        buffer.write_u64(0);
+        let mut writer = Self { writer, coins_count: 0 };
+        writer.writer.write_all(&buffer.buffer()).unwrap();
+        writer
+    }
+
+    pub fn write_coins(&mut self, hash: [u8; 32], coins: &[(u32, SnapshotCoin)]) {
+        let mut buffer = BufferWriter::new();
+        buffer.write_hash(hash);
+        buffer.write_var_int_le(coins.len().try_into().unwrap());
+        for (index, coin) in coins {
+            buffer.write_var_int_le((*index).into());
+            buffer.write_b128_var_int((u64::from(coin.height) << 1) | u64::from(coin.coinbase));
+            buffer.write_b128_var_int(compress_amount(coin.output.value));
+            match compress_script(&coin.output.script) {
+                Some(script) => buffer.write_buffer(&script),
+                None => {
+                    buffer.write_b128_var_int(coin.output.script.len() as u64 + SPECIAL_SCRIPTS);
+                    buffer.write_buffer(&coin.output.script);
+                }
+            }
+        }
+        self.writer.write_all(&buffer.buffer()).unwrap();
+        self.coins_count += coins.len() as u64;
+    }
+
+    pub fn finish(mut self) -> u64 {
+        self.writer.seek(SeekFrom::Start(COINS_COUNT_OFFSET)).unwrap();
+        self.writer.write_all(&self.coins_count.to_le_bytes()).unwrap();
+        self.writer.flush().unwrap();
+        self.coins_count
+    }
+}
@@ -1,32 +1,146 @@
-use crate::store::{ReadonlyAugmentedTransactionStoreBackend, ReadonlyTransactionStore};
+use crate::balance_stats::BalanceBucket;
+use crate::fees::{self, BlockFeeStats, FeerateBucket, ProjectedBlock, TailFees, TransactionFee, FEERATE_TARGETS, PROJECTED_BLOCKS};
+use crate::last_blocks::{self, BlocksUpdate};
+use crate::mempool::{MempoolEntry, MempoolSnapshot, MempoolTransaction, MEMPOOL_SHARDS};
+use crate::store::{ReadonlyAugmentedTransactionStoreBackend, ReadonlyTransactionStore, ReadonlyTransactionStoreBackendTrait};
 use crate::{
-    Address, Arc, AugmentedStore, Block, Client, IndexedStore, IndexedTransactionStoreBackend, LastBlocks, Logger, Mutex, RwLock,
-    TransactionOutput, TransactionStoreAugmentation,
+    store::{AddressStats, Coin, HistoryEntry, OpReturn, Spender, UtxoSetStats, MEMPOOL_HEIGHT},
+    Address, Arc, AugmentedTransactionStore, BalanceStats, Block, BlockHeader, BlockTrait, Client, Error, HashSet, IndexedStore,
+    IndexedTransactionStoreBackend, LastBlocks, Logger, Mutex, RwLock, TransactionOutput, TransactionStoreAugmentation,
+    TransactionStoreBackendTrait, TryInto,
 };
 
-enum Update {
-    LastAugmentationUpdate(TransactionStoreAugmentation),
-    AugmentationsUpdate(Vec<TransactionStoreAugmentation>, Vec<Block>),
+/// Number of block times requested from the node in a single batch.
+const BLOCK_TIMES_BATCH_SIZE: usize = 2000;
+
+/// Outputs of the blocks and mempool transactions that are not part of the store, with the store they build on. Each block
+/// and the mempool get their own layer stacked on the previous ones, so that an update only computes the layers of the new
+/// blocks and applies the mempool changes to the mempool layer, and cloning it only shares the layers.
+#[derive(Clone)]
+struct Augmentations {
+    /// Store shared with the queries, replaced by a copy with the confirmed blocks once they are added to it.
+    store: Arc<IndexedStore>,
+    /// Outputs of each confirmed block waiting to be added to the store, oldest first.
+    confirmed: Vec<Arc<TransactionStoreAugmentation>>,
+    confirmed_block_headers: Vec<BlockHeader>,
+    /// Outputs of each unconfirmed block, oldest first.
+    unconfirmed: Vec<Arc<TransactionStoreAugmentation>>,
+    /// Outputs of the mempool transactions, on top of all the blocks.
+    mempool_augmentation: TransactionStoreAugmentation,
+    /// Fees of the transactions of the confirmed and unconfirmed blocks and of the mempool.
+    fees: TailFees,
+    mempool: MempoolSnapshot,
+}
+
+impl Augmentations {
+    fn new(store: Arc<IndexedStore>) -> Self {
+        let mempool_augmentation = TransactionStoreAugmentation::with_shards(&store.backend().index_options(), MEMPOOL_SHARDS);
+        Self {
+            store,
+            confirmed: Vec::new(),
+            confirmed_block_headers: Vec::new(),
+            unconfirmed: Vec::new(),
+            mempool_augmentation,
+            fees: TailFees::new(),
+            mempool: MempoolSnapshot::new(),
+        }
+    }
+
+    /// Augmentations built from scratch with all the blocks and mempool transactions of `tail_blocks`.
+    fn from_tail_blocks(store: Arc<IndexedStore>, tail_blocks: &LastBlocks) -> Self {
+        let mut augmentations = Self::new(store);
+        for block in tail_blocks.confirmed_blocks().iter().chain(tail_blocks.blocks()) {
+            augmentations.add_block(block);
+        }
+        augmentations.confirm(tail_blocks.confirmed_blocks());
+        augmentations.add_mempool_transactions(tail_blocks.mempool_transactions().cloned().collect());
+        augmentations
+    }
+
+    /// Adds the layer of a block following the last one, which the mempool layer must not spend from.
+    fn add_block(&mut self, block: &Block) {
+        let layers = self.confirmed.iter().chain(&self.unconfirmed).map(|layer| &**layer).collect();
+        let mut augmented_store = AugmentedTransactionStore::with_layers(self.store.transaction_store(), layers);
+        let fees = augmented_store.add_block_with_fees(block);
+        let layer = augmented_store.take_augmentation();
+        self.fees.add_block(block.height(), block.hash(), fees);
+        self.unconfirmed.push(Arc::new(layer));
+    }
+
+    /// Moves the layers of the blocks that got confirmed to the confirmed layers.
+    fn confirm(&mut self, confirmed_blocks: &[Block]) {
+        let count = confirmed_blocks.len() - self.confirmed.len();
+        self.confirmed.extend(self.unconfirmed.drain(..count));
+        self.confirmed_block_headers = confirmed_blocks.iter().map(|block| block.header().clone()).collect();
+    }
+
+    /// Removes the mempool transactions with the ones spending their outputs, directly or not. Returns the transactions
+    /// removed that are not among `hashes`, parents first, to be added again. Fails when the mempool layer does not match
+    /// the transactions, leaving the augmentations partially updated.
+    fn remove_mempool_transactions(&mut self, hashes: &HashSet<[u8; 32]>) -> Result<Vec<Arc<MempoolTransaction>>, Error> {
+        let removed = self.mempool.with_descendants(hashes.iter().copied());
+        let entries: Vec<_> = removed
+            .iter()
+            .rev()
+            .map(|hash| {
+                self.fees.remove_mempool_transaction(hash);
+                self.mempool.remove(hash).unwrap()
+            })
+            .collect();
+        self.mempool_augmentation.remove_transactions(
+            entries
+                .iter()
+                .map(|entry| (&entry.transaction.transaction, entry.spent_coins.as_slice())),
+        )?;
+        self.mempool.remove_orphans(hashes);
+        Ok(entries
+            .into_iter()
+            .rev()
+            .filter(|entry| !hashes.contains(&entry.transaction.transaction.hash))
+            .map(|entry| entry.transaction)
+            .collect())
+    }
+
+    /// Adds mempool transactions, parents first, to the mempool layer along with the ones left out so far.
+    fn add_mempool_transactions(&mut self, mut transactions: Vec<Arc<MempoolTransaction>>) {
+        transactions.extend(self.mempool.take_orphans());
+        let augmentation = std::mem::take(&mut self.mempool_augmentation);
+        let layers = self.confirmed.iter().chain(&self.unconfirmed).map(|layer| &**layer).collect();
+        let mut augmented_store = AugmentedTransactionStore::with_augmentation(self.store.transaction_store(), layers, augmentation);
+        let orphans = last_blocks::add_mempool_transactions(&mut augmented_store, transactions, &mut self.fees, &mut self.mempool);
+        self.mempool_augmentation = augmented_store.take_augmentation();
+        self.mempool.set_orphans(orphans);
+    }
+}
+
+/// Block of the store selected by its height or by a time.
+pub enum BlockSelector {
+    Height(usize),
+    /// Selects the block before the first block with a later timestamp, as block timestamps are not strictly increasing.
+    Time(u32),
+}
+
+/// Whether the transaction of a mempool output can be replaced, `None` for the outputs of blocks.
+fn replaceable(mempool: &MempoolSnapshot, txo: &TransactionOutput, coin: &Coin) -> Option<bool> {
+    (coin.height == MEMPOOL_HEIGHT).then(|| matches!(mempool.transaction(&txo.hash), Some(entry) if entry.replaceable))
 }
 
 pub struct State {
-    store: Arc<RwLock<IndexedStore>>,
     tail_blocks: Mutex<LastBlocks>,
-    augmentations: RwLock<Vec<TransactionStoreAugmentation>>,
+    augmentations: RwLock<Augmentations>,
     client: Client,
-    mutex: Mutex<()>,
     confirmations: usize,
+    balance_stats: RwLock<Option<BalanceStats>>,
 }
 
 impl State {
     pub fn new(store: IndexedStore, client: Client, confirmations: usize) -> Self {
         Self {
-            augmentations: RwLock::new(Vec::new()),
-            store: Arc::new(RwLock::new(store)),
+            augmentations: RwLock::new(Augmentations::new(Arc::new(store))),
             tail_blocks: Mutex::new(LastBlocks::new()),
             client,
-            mutex: Mutex::new(()),
             confirmations,
+            balance_stats: RwLock::new(None),
         }
     }
 
@@ -34,138 +148,488 @@ impl State {
         self.confirmations
     }
 
-    async fn compute_update(&self, update_store: bool, logger: &Logger) -> Update {
-        let store = self.store.read().await;
+    /// Updates a copy of the augmentations with the changes of the blocks and of the mempool, `None` when nothing changed.
+    /// The layers of the new blocks are computed on top of the current ones and the mempool transactions that left the
+    /// mempool, or that the new blocks confirm or conflict with, are removed from the mempool layer with their descendants,
+    /// so that only a reorganization computes every layer again.
+    async fn compute_update(&self, logger: &Logger) -> Option<Augmentations> {
         let mut tail_blocks = self.tail_blocks.lock().await;
-        tail_blocks.update_mempool(&self.client, logger).await;
-        if tail_blocks.update_blocks(&store, &self.client, logger).await {
-            let blocks = if update_store {
-                tail_blocks.pop(self.confirmations)
-            } else {
-                Vec::new()
-            };
-            let augmentations = if blocks.len() > 0 {
-                let mut store = AugmentedStore::new(&store);
-                for block in &blocks {
-                    store.add_block(block);
+        let (added, removed) = tail_blocks.update_mempool(&self.client, logger).await;
+        let store = self.store().await;
+        let blocks_update = tail_blocks.update_blocks(&store, &self.client, logger).await;
+        let mut augmentations = match blocks_update {
+            BlocksUpdate::Unchanged if added.is_empty() && removed.is_empty() => return None,
+            BlocksUpdate::Reorganized => {
+                tail_blocks.confirm(self.confirmations);
+                Augmentations::from_tail_blocks(store, &tail_blocks)
+            }
+            BlocksUpdate::Unchanged | BlocksUpdate::Extended(_) => {
+                let mut augmentations = self.augmentations.read().await.clone();
+                let count = match blocks_update {
+                    BlocksUpdate::Extended(count) => count,
+                    _ => 0,
+                };
+                let blocks = tail_blocks.blocks();
+                let new_blocks: Vec<_> = blocks.range(blocks.len() - count..).collect();
+                let mut removed: HashSet<_> = removed.into_iter().collect();
+                for block in &new_blocks {
+                    block.transactions(&mut |transaction| removed.extend(augmentations.mempool.conflicts(transaction)));
                 }
-                tail_blocks.augmentations(&store)
-            } else {
-                tail_blocks.augmentations(&store)
-            };
-            assert!(augmentations.len() == self.confirmations);
-            Update::AugmentationsUpdate(augmentations, blocks)
-        } else {
-            Update::LastAugmentationUpdate(tail_blocks.last_augmentation(&store))
+                match augmentations.remove_mempool_transactions(&removed) {
+                    Ok(mut transactions) => {
+                        for block in new_blocks {
+                            augmentations.add_block(block);
+                        }
+                        tail_blocks.confirm(self.confirmations);
+                        augmentations.confirm(tail_blocks.confirmed_blocks());
+                        transactions.extend(added);
+                        augmentations.add_mempool_transactions(transactions);
+                        augmentations
+                    }
+                    Err(error) => {
+                        logger.log(format!(
+                            "removing mempool transactions failed, rebuilding the augmentations: {}",
+                            error
+                        ));
+                        tail_blocks.confirm(self.confirmations);
+                        Augmentations::from_tail_blocks(store, &tail_blocks)
+                    }
+                }
+            }
+        };
+        if blocks_update != BlocksUpdate::Unchanged {
+            assert!(augmentations.unconfirmed.len() + 1 == self.confirmations);
         }
+        augmentations.mempool.set_replacements(tail_blocks.replacements());
+        Some(augmentations)
     }
 
-    async fn apply_update(&self, update: Update) -> bool {
+    /// Swaps the updated augmentations in, dropping the previous ones after releasing the lock.
+    async fn apply_update(&self, updated_augmentations: Augmentations, logger: &Logger) {
         let mut augmentations = self.augmentations.write().await;
-        match update {
-            Update::LastAugmentationUpdate(augmentation) => {
-                let index = augmentations.len() - 1;
-                augmentations[index] = augmentation;
-                false
+        let previous_augmentations = std::mem::replace(&mut *augmentations, updated_augmentations);
+        let orphans = augmentations.mempool.orphans();
+        drop(augmentations);
+        drop(previous_augmentations);
+        if orphans > 0 {
+            logger.log(format!("{} mempool transactions spend unknown outputs", orphans));
+        }
+    }
+
+    /// Adds the confirmed blocks to a copy of the store on a blocking thread, the copy only duplicating the shards the
+    /// blocks modify, then swaps it for the store and the confirmed layers. Queries keep reading the previous store
+    /// meanwhile, which is dropped once the last of them is done with it.
+    async fn add_confirmed_blocks(&self, logger: &Logger) -> bool {
+        let mut tail_blocks = self.tail_blocks.lock().await;
+        let blocks = tail_blocks.confirmed_blocks().to_vec();
+        if blocks.is_empty() {
+            return false;
+        }
+        logger.log(format!("adding {} confirmed blocks to the store...", blocks.len()));
+        let mut fees = self.augmentations.read().await.fees.clone();
+        for block in &blocks {
+            fees.remove_block(block);
+        }
+        let store = self.store().await;
+        let store = tokio::task::spawn_blocking(move || {
+            let mut store = (*store).clone();
+            for block in &blocks {
+                store.add_block(block);
             }
-            Update::AugmentationsUpdate(updated_augmentations, blocks) => {
-                *augmentations = updated_augmentations;
-                if blocks.len() > 0 {
-                    let store = &mut *self.store.write().await;
-                    for block in &blocks {
-                        store.add_block(block);
-                    }
-                    true
-                } else {
-                    false
-                }
+            store
+        })
+        .await
+        .unwrap();
+        let mut augmentations = self.augmentations.write().await;
+        let previous_store = std::mem::replace(&mut augmentations.store, Arc::new(store));
+        let previous_layers = std::mem::take(&mut augmentations.confirmed);
+        augmentations.confirmed_block_headers.clear();
+        augmentations.fees = fees;
+        drop(augmentations);
+        drop(previous_store);
+        drop(previous_layers);
+        tail_blocks.take_confirmed_blocks();
+        logger.log("adding confirmed blocks to the store done!");
+        true
+    }
+
+    /// Requests from the node the times of the blocks of the store that have none, after loading a store file written before
+    /// block times were stored. Returns whether any block time was added.
+    pub async fn add_block_times(&self) -> bool {
+        let logger = Logger::new();
+        let store = self.store().await;
+        let blocks = store.blocks_without_time();
+        if blocks.is_empty() {
+            return false;
+        }
+        logger.log(format!("requesting the times of {} blocks...", blocks.len()));
+        let mut store = (*store).clone();
+        for blocks in blocks.chunks(BLOCK_TIMES_BATCH_SIZE) {
+            let hashes: Vec<_> = blocks.iter().map(|(_, hash)| *hash).collect();
+            let times = self.client.getblockheader_times(&hashes, &logger).await;
+            for ((height, _), time) in blocks.iter().zip(times) {
+                store.set_block_time(*height, time);
             }
         }
+        self.augmentations.write().await.store = Arc::new(store);
+        logger.log("requesting the times of blocks done!");
+        true
     }
 
     pub async fn update(&self) -> bool {
-        let mutex = self.mutex.try_lock();
         let logger = Logger::new();
         logger.log("computing update...");
-        let update = self.compute_update(mutex.is_ok(), &logger).await;
+        let update = self.compute_update(&logger).await;
         logger.log("computing update done!");
         let logger = Logger::new();
         logger.log("applying update...");
-        let result = self.apply_update(update).await;
+        if let Some(update) = update {
+            self.apply_update(update, &logger).await;
+        }
+        let result = self.add_confirmed_blocks(&logger).await;
         logger.log("applying update done!");
         result
     }
 
+    /// Current store, which stays readable without holding any lock while the next one is built.
+    async fn store(&self) -> Arc<IndexedStore> {
+        self.augmentations.read().await.store.clone()
+    }
+
     fn augmented_store<'a>(
         &self,
-        store: &'a IndexedStore,
-        augmentations: &'a Vec<TransactionStoreAugmentation>,
+        augmentations: &'a Augmentations,
         confirmations: usize,
     ) -> ReadonlyTransactionStore<ReadonlyAugmentedTransactionStoreBackend<'a, IndexedTransactionStoreBackend>> {
-        if confirmations == augmentations.len() {
-            ReadonlyTransactionStore::new(ReadonlyAugmentedTransactionStoreBackend::new(store.backend(), None))
-        } else {
-            assert!(confirmations < augmentations.len());
-            let augmentation = &augmentations[augmentations.len() - 1 - confirmations];
-            ReadonlyTransactionStore::new(ReadonlyAugmentedTransactionStoreBackend::new(store.backend(), Some(augmentation)))
+        let unconfirmed = &augmentations.unconfirmed;
+        assert!(confirmations <= unconfirmed.len() + 1);
+        let count = (unconfirmed.len() + 1).saturating_sub(confirmations.max(1));
+        let mut layers: Vec<_> = augmentations
+            .confirmed
+            .iter()
+            .chain(&unconfirmed[..count])
+            .map(|layer| &**layer)
+            .collect();
+        if confirmations == 0 {
+            layers.push(&augmentations.mempool_augmentation);
         }
+        ReadonlyTransactionStore::new(ReadonlyAugmentedTransactionStoreBackend::new(augmentations.store.backend(), layers))
+    }
+
+    fn tip_height(&self, augmentations: &Augmentations) -> u32 {
+        (augmentations.store.height() + augmentations.confirmed.len() + augmentations.unconfirmed.len() - 1)
+            .try_into()
+            .unwrap()
     }
 
+    /// Iterates over the outputs of the address with their confirmations and, for mempool outputs, whether their
+    /// transaction can be replaced.
     pub async fn iterate_transaction_outputs(
         &self,
         address: &Address,
         confirmations: usize,
-        callback: impl FnMut(&TransactionOutput, u64),
+        mut callback: impl FnMut(&TransactionOutput, &Coin, u32, Option<bool>),
     ) {
         let augmentations = self.augmentations.read().await;
-        let store = self.store.read().await;
-        let store = self.augmented_store(&store, &augmentations, confirmations);
-        store.iterate_transaction_outputs(address, callback);
+        let tip_height = self.tip_height(&augmentations);
+        let mempool = &augmentations.mempool;
+        let store = self.augmented_store(&augmentations, confirmations);
+        store.iterate_transaction_outputs(address, |txo, coin| {
+            callback(txo, coin, coin.confirmations(tip_height), replaceable(mempool, txo, coin))
+        });
     }
 
     pub async fn transaction_outputs_array(
         &self,
         addresses: Vec<Address>,
         confirmations: usize,
-    ) -> Vec<(Address, Vec<(TransactionOutput, u64)>)> {
+    ) -> Vec<(Address, Vec<(TransactionOutput, Coin, u32, Option<bool>)>)> {
         let augmentations = self.augmentations.read().await;
-        let store = self.store.read().await;
-        let store = self.augmented_store(&store, &augmentations, confirmations);
+        let tip_height = self.tip_height(&augmentations);
+        let mempool = &augmentations.mempool;
+        let store = self.augmented_store(&augmentations, confirmations);
         addresses
             .into_iter()
             .map(|address| {
                 let mut utxos = Vec::new();
-                store.iterate_transaction_outputs(&address, |utxo, value| {
-                    utxos.push((utxo.clone(), value));
+                store.iterate_transaction_outputs(&address, |utxo, coin| {
+                    let replaceable = replaceable(mempool, utxo, coin);
+                    utxos.push((utxo.clone(), coin.clone(), coin.confirmations(tip_height), replaceable));
                 });
                 (address, utxos)
             })
             .collect()
     }
 
-    pub async fn balance(&self, address: &Address, confirmations: usize) -> u64 {
+    /// Outputs of the address from the last transactions replaced in the mempool, newest first, with the hash of the
+    /// transaction that replaced them.
+    pub async fn replaced_transaction_outputs(&self, address: &Address) -> Vec<(TransactionOutput, u64, [u8; 32])> {
         let augmentations = self.augmentations.read().await;
-        let store = self.store.read().await;
-        let store = self.augmented_store(&store, &augmentations, confirmations);
-        store.balance(address)
+        let mut txos = Vec::new();
+        augmentations.mempool.iterate_replaced_outputs(address, |txo, value, replaced_by| {
+            txos.push((txo.clone(), value, *replaced_by));
+        });
+        txos
     }
 
-    pub async fn balance_array(&self, addresses: Vec<Address>, confirmations: usize) -> Vec<(Address, u64)> {
+    pub async fn unspent_transaction_outputs(
+        &self,
+        txos: Vec<TransactionOutput>,
+        confirmations: usize,
+    ) -> Vec<(TransactionOutput, Option<(Coin, u32)>)> {
+        let augmentations = self.augmentations.read().await;
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
+        txos.into_iter()
+            .map(|txo| {
+                let coin = store
+                    .unspent_transaction_output(&txo)
+                    .map(|coin| (coin.clone(), coin.confirmations(tip_height)));
+                (txo, coin)
+            })
+            .collect()
+    }
+
+    pub async fn balance(&self, address: &Address, confirmations: usize, exclude_immature_coinbase: bool) -> u64 {
         let augmentations = self.augmentations.read().await;
-        let store = self.store.read().await;
-        let store = self.augmented_store(&store, &augmentations, confirmations);
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
+        match exclude_immature_coinbase {
+            true => store.mature_balance(address, tip_height),
+            false => store.balance(address),
+        }
+    }
+
+    pub async fn balance_array(
+        &self,
+        addresses: Vec<Address>,
+        confirmations: usize,
+        exclude_immature_coinbase: bool,
+    ) -> Vec<(Address, u64)> {
+        let augmentations = self.augmentations.read().await;
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
         addresses
             .into_iter()
             .map(|address| {
-                let balance = store.balance(&address);
+                let balance = match exclude_immature_coinbase {
+                    true => store.mature_balance(&address, tip_height),
+                    false => store.balance(&address),
+                };
                 (address, balance)
             })
             .collect()
     }
 
+    pub async fn history(
+        &self,
+        address: &Address,
+        confirmations: usize,
+        offset: usize,
+        limit: usize,
+    ) -> Option<(usize, Vec<(HistoryEntry, u32)>)> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        if !store.backend().index_options().history {
+            return None;
+        }
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
+        let mut entries = Vec::new();
+        store.iterate_history(address, offset, limit, |entry| {
+            entries.push((entry.clone(), crate::store::confirmations(entry.height, tip_height)));
+        });
+        Some((store.history_len(address), entries))
+    }
+
+    pub async fn address_stats(&self, address: &Address, confirmations: usize) -> Option<Option<AddressStats>> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        if !store.backend().index_options().stats {
+            return None;
+        }
+        let store = self.augmented_store(&augmentations, confirmations);
+        Some(store.address_stats(address))
+    }
+
+    pub async fn spender(&self, txo: &TransactionOutput, confirmations: usize) -> Option<Option<(Spender, u32)>> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        if !store.backend().index_options().spends {
+            return None;
+        }
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
+        let spender = store.spender(txo).cloned();
+        Some(spender.map(|spender| {
+            let confirmations = crate::store::confirmations(spender.height, tip_height);
+            (spender, confirmations)
+        }))
+    }
+
+    /// OP_RETURN outputs whose payload starts with `prefix`, newest first, with their total number.
+    pub async fn op_returns(
+        &self,
+        prefix: &[u8],
+        confirmations: usize,
+        offset: usize,
+        limit: usize,
+    ) -> Option<(usize, Vec<(OpReturn, u32)>)> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        if !store.backend().index_options().op_returns {
+            return None;
+        }
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
+        let mut entries = Vec::new();
+        let count = store.iterate_op_returns(prefix, offset, limit, |entry| {
+            entries.push((entry.clone(), crate::store::confirmations(entry.height, tip_height)));
+        });
+        Some((count, entries))
+    }
+
+    /// OP_RETURN outputs whose payload has the SHA256 `hash`, newest first.
+    pub async fn op_returns_by_hash(&self, hash: &[u8; 32], confirmations: usize) -> Option<Vec<(OpReturn, u32)>> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        if !store.backend().index_options().op_returns {
+            return None;
+        }
+        let tip_height = self.tip_height(&augmentations);
+        let store = self.augmented_store(&augmentations, confirmations);
+        let mut entries = Vec::new();
+        store.iterate_op_returns_by_hash(hash, |entry| {
+            entries.push((entry.clone(), crate::store::confirmations(entry.height, tip_height)));
+        });
+        Some(entries)
+    }
+
+    /// Fee of a transaction of the blocks that are not part of the store yet or of the mempool, with its height and
+    /// confirmations.
+    pub async fn transaction_fee(&self, hash: &[u8; 32]) -> Option<(TransactionFee, u32, u32)> {
+        let augmentations = self.augmentations.read().await;
+        let tip_height = self.tip_height(&augmentations);
+        let (fee, height) = augmentations.fees.transaction(hash)?;
+        Some((fee.clone(), *height, crate::store::confirmations(*height, tip_height)))
+    }
+
+    /// Fee stats of the blocks that are not part of the store yet, oldest first, and of the mempool.
+    pub async fn fee_stats(&self) -> (Vec<(usize, [u8; 32], BlockFeeStats)>, BlockFeeStats) {
+        let augmentations = self.augmentations.read().await;
+        (augmentations.fees.blocks().to_vec(), augmentations.fees.mempool())
+    }
+
+    pub async fn feerate_histogram(&self) -> Vec<FeerateBucket> {
+        let augmentations = self.augmentations.read().await;
+        fees::feerate_histogram(&augmentations.fees.mempool_fees())
+    }
+
+    /// Feerates recommended for each of `FEERATE_TARGETS` with the blocks projected from the mempool.
+    pub async fn recommended_feerates(&self) -> (Vec<f64>, Vec<ProjectedBlock>) {
+        let augmentations = self.augmentations.read().await;
+        let mempool_fees = augmentations.fees.mempool_fees();
+        (
+            fees::recommended_feerates(&mempool_fees, &FEERATE_TARGETS),
+            fees::projected_blocks(&mempool_fees, PROJECTED_BLOCKS),
+        )
+    }
+
+    /// Mempool transaction with the coins it spends and its fee, as of the last update.
+    pub async fn mempool_transaction(&self, hash: &[u8; 32]) -> Option<(MempoolEntry, TransactionFee)> {
+        let augmentations = self.augmentations.read().await;
+        let entry = augmentations.mempool.transaction(hash)?;
+        let (fee, _) = augmentations.fees.transaction(hash)?;
+        Some((entry.clone(), fee.clone()))
+    }
+
+    /// Number of mempool transactions with their total virtual size and fees, and number of orphaned transactions, as of
+    /// the last update.
+    pub async fn mempool_summary(&self) -> (usize, usize, u64, usize) {
+        let augmentations = self.augmentations.read().await;
+        let fees = augmentations.fees.mempool();
+        let mempool = &augmentations.mempool;
+        (mempool.len(), mempool.vsize(), fees.total_fees, mempool.orphans())
+    }
+
+    /// Block selected among the confirmed blocks, including those not yet added to the store, with the outputs held by the
+    /// address at the end of it. `None` when the history index is disabled, `Some(None)` when no block is selected.
+    pub async fn historical_transaction_outputs(
+        &self,
+        address: &Address,
+        selector: &BlockSelector,
+    ) -> Option<Option<(usize, BlockHeader, Vec<(TransactionOutput, u64, u32)>)>> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        if !store.backend().index_options().history {
+            return None;
+        }
+        let block_headers: Vec<_> = store.block_headers().iter().chain(&augmentations.confirmed_block_headers).collect();
+        let height = match selector {
+            BlockSelector::Height(height) => Some(*height).filter(|height| *height < block_headers.len()),
+            BlockSelector::Time(time) => match block_headers.iter().position(|block_header| block_header.time > *time) {
+                Some(height) => height.checked_sub(1),
+                None => block_headers.len().checked_sub(1),
+            },
+        };
+        let backend =
+            ReadonlyAugmentedTransactionStoreBackend::new(store.backend(), augmentations.confirmed.iter().map(|layer| &**layer).collect());
+        Some(height.map(|height| {
+            let txos = backend.historical_transaction_outputs(address, height.try_into().unwrap());
+            (height, block_headers[height].clone(), txos)
+        }))
+    }
+
+    pub async fn utxo_set_stats(&self) -> Option<(usize, [u8; 32], UtxoSetStats, usize)> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        let backend = store.backend();
+        let hash = store.last_block_hash()?;
+        Some((
+            store.height() - 1,
+            hash,
+            backend.utxo_set_stats().clone(),
+            backend.funded_addresses(),
+        ))
+    }
+
+    pub async fn muhash(&self) -> Option<(usize, [u8; 32], [u8; 32])> {
+        let augmentations = self.augmentations.read().await;
+        let store = &augmentations.store;
+        let hash = store.last_block_hash()?;
+        let muhash = store.backend().muhash().unwrap().finalize();
+        Some((store.height() - 1, hash, muhash))
+    }
+
+    pub async fn node_muhash(&self, hash: &[u8; 32], logger: &Logger) -> Option<[u8; 32]> {
+        self.client.gettxoutsetinfo_muhash(hash, logger).await
+    }
+
+    /// Computes the balance stats of the current store on a blocking thread, without holding any lock meanwhile.
+    pub async fn update_balance_stats(&self) {
+        let logger = Logger::new();
+        logger.log("computing balance stats...");
+        let store = self.store().await;
+        let balance_stats = tokio::task::spawn_blocking(move || BalanceStats::new(&store)).await.unwrap();
+        *self.balance_stats.write().await = Some(balance_stats);
+        logger.log("computing balance stats done!");
+    }
+
+    pub async fn rich_list(&self, limit: usize) -> Option<(usize, [u8; 32], Vec<(Address, u64)>)> {
+        let balance_stats = self.balance_stats.read().await;
+        let balance_stats = balance_stats.as_ref()?;
+        let rich_list = balance_stats.rich_list.iter().take(limit).cloned().collect();
+        Some((balance_stats.height, balance_stats.hash, rich_list))
+    }
+
+    pub async fn balance_distribution(&self) -> Option<(usize, [u8; 32], Vec<BalanceBucket>)> {
+        let balance_stats = self.balance_stats.read().await;
+        let balance_stats = balance_stats.as_ref()?;
+        Some((balance_stats.height, balance_stats.hash, balance_stats.distribution.clone()))
+    }
+
     pub async fn write(&self, file: &str) {
-        let _ = self.mutex.lock().await;
-        let store = self.store.read().await;
+        let store = self.store().await;
         let logger = Logger::new();
         store.to_file(file, &logger);
     }
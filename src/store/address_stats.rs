@@ -0,0 +1,153 @@
+use crate::{BufferWriter, Error, HashingBufferReader, ParseResult, ParseResultExt};
+
+/// Lifetime totals of the outputs funded and spent by an address.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct AddressStats {
+    pub received: u64,
+    pub sent: u64,
+    pub funded_outputs: u64,
+    pub spent_outputs: u64,
+    pub first_seen: u32,
+    pub last_seen: u32,
+}
+
+/// Total and count without an output of `value`.
+fn undo(total: u64, count: u64, value: u64) -> Result<(u64, u64), Error> {
+    match (total.checked_sub(value), count.checked_sub(1)) {
+        (Some(total), Some(count)) => Ok((total, count)),
+        _ => Err(Error::new(format!("Address stats do not count an output of {} sat", value))),
+    }
+}
+
+impl AddressStats {
+    pub fn new(height: u32) -> Self {
+        Self {
+            received: 0,
+            sent: 0,
+            funded_outputs: 0,
+            spent_outputs: 0,
+            first_seen: height,
+            last_seen: height,
+        }
+    }
+
+    fn see(&mut self, height: u32) {
+        self.first_seen = self.first_seen.min(height);
+        self.last_seen = self.last_seen.max(height);
+    }
+
+    pub fn receive(&mut self, value: u64, height: u32) {
+        self.received += value;
+        self.funded_outputs += 1;
+        self.see(height);
+    }
+
+    pub fn send(&mut self, value: u64, height: u32) {
+        self.sent += value;
+        self.spent_outputs += 1;
+        self.see(height);
+    }
+
+    /// Undoes `receive`, keeping the heights at which the address was seen. Fails without changing the stats when they do
+    /// not count the output.
+    pub fn undo_receive(&mut self, value: u64) -> Result<(), Error> {
+        let (received, funded_outputs) = undo(self.received, self.funded_outputs, value)?;
+        self.received = received;
+        self.funded_outputs = funded_outputs;
+        Ok(())
+    }
+
+    /// Undoes `send`, keeping the heights at which the address was seen. Fails without changing the stats when they do not
+    /// count the output.
+    pub fn undo_send(&mut self, value: u64) -> Result<(), Error> {
+        let (sent, spent_outputs) = undo(self.sent, self.spent_outputs, value)?;
+        self.sent = sent;
+        self.spent_outputs = spent_outputs;
+        Ok(())
+    }
+
+    /// Whether no output funding or spent by the address is counted.
+    pub fn is_empty(&self) -> bool {
+        self.funded_outputs == 0 && self.spent_outputs == 0
+    }
+
+    /// Combines the stats of the same address over two ranges of blocks.
+    pub fn merge(&self, other: &AddressStats) -> Self {
+        Self {
+            received: self.received + other.received,
+            sent: self.sent + other.sent,
+            funded_outputs: self.funded_outputs + other.funded_outputs,
+            spent_outputs: self.spent_outputs + other.spent_outputs,
+            first_seen: self.first_seen.min(other.first_seen),
+            last_seen: self.last_seen.max(other.last_seen),
+        }
+    }
+
+    pub fn from_reader(reader: &mut HashingBufferReader) -> ParseResult<Self> {
+        Ok(Self {
+            received: reader.read_b128_var_int(&mut None).field("received")?,
+            sent: reader.read_b128_var_int(&mut None).field("sent")?,
+            funded_outputs: reader.read_b128_var_int(&mut None).field("funded outputs")?,
+            spent_outputs: reader.read_b128_var_int(&mut None).field("spent outputs")?,
+            first_seen: reader.read_b128_var_int_as(&mut None).field("first seen")?,
+            last_seen: reader.read_b128_var_int_as(&mut None).field("last seen")?,
+        })
+    }
+
+    pub fn to_writer(&self, writer: &mut BufferWriter) {
+        writer.write_b128_var_int(self.received);
+        writer.write_b128_var_int(self.sent);
+        writer.write_b128_var_int(self.funded_outputs);
+        writer.write_b128_var_int(self.spent_outputs);
+        writer.write_b128_var_int(self.first_seen.into());
+        writer.write_b128_var_int(self.last_seen.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AddressStats;
+    use crate::{BufferWriter, HashingBufferReader};
+
+    #[test]
+    fn merge() {
+        let mut base = AddressStats::new(100);
+        base.receive(5_000, 100);
+        base.receive(2_000, 150);
+        base.send(5_000, 160);
+        let mut tail = AddressStats::new(170);
+        tail.send(2_000, 170);
+        let stats = base.merge(&tail);
+        assert_eq!(stats.received, 7_000);
+        assert_eq!(stats.sent, 7_000);
+        assert_eq!(stats.funded_outputs, 2);
+        assert_eq!(stats.spent_outputs, 2);
+        assert_eq!((stats.first_seen, stats.last_seen), (100, 170));
+        assert_eq!(tail.merge(&base), stats);
+    }
+
+    #[test]
+    fn undo() {
+        let mut stats = AddressStats::new(100);
+        stats.receive(5_000, 100);
+        stats.send(5_000, 110);
+        assert!(stats.undo_send(6_000).is_err());
+        assert!(stats.undo_send(5_000).is_ok());
+        assert!(stats.undo_send(0).is_err());
+        assert_eq!((stats.sent, stats.spent_outputs), (0, 0));
+        assert!(stats.undo_receive(5_000).is_ok());
+        assert!(stats.is_empty());
+        assert!(stats.undo_receive(1).is_err());
+    }
+
+    #[test]
+    fn from_reader() {
+        let mut stats = AddressStats::new(700_000);
+        stats.receive(21_000_000 * 100_000_000, 700_000);
+        stats.send(1, 800_000);
+        let mut writer = BufferWriter::new();
+        stats.to_writer(&mut writer);
+        let buffer = writer.buffer();
+        assert_eq!(AddressStats::from_reader(&mut HashingBufferReader::new(&buffer)), Ok(stats));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    compression::MAX_SCRIPT_SIZE,
+    script::{self, ScriptType},
+    Address, BufferWriter, HashingBufferReader, ParseError, ParseErrorKind, ParseResult, ParseResultExt, TransactionOutput, TryInto,
+};
+
+/// Height given to the outputs of mempool transactions, like Bitcoin Core does.
+pub const MEMPOOL_HEIGHT: u32 = 0x7FFFFFFF;
+/// Number of confirmations after which a coinbase output can be spent.
+pub const COINBASE_MATURITY: u32 = 100;
+
+const SCRIPT_P2PKH: u8 = 0;
+const SCRIPT_P2SH: u8 = 1;
+const SCRIPT_P2WPKH: u8 = 2;
+const SCRIPT_P2WSH: u8 = 3;
+const SCRIPT_P2TR: u8 = 4;
+const SCRIPT_P2PK: u8 = 5;
+const SCRIPT_OTHER: u8 = 6;
+
+/// Names of the script types, indexed by `CompactScript::script_type`.
+pub const SCRIPT_TYPES: [&str; 7] = ["p2pkh", "p2sh", "p2wpkh", "p2wsh", "p2tr", "p2pk", "other"];
+
+/// Output script with the common templates stored inline.
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub enum CompactScript {
+    P2PKH([u8; 20]),
+    P2SH([u8; 20]),
+    P2WPKH([u8; 20]),
+    P2WSH([u8; 32]),
+    P2TR([u8; 32]),
+    P2PK([u8; 33]),
+    Other(Box<[u8]>),
+}
+
+impl CompactScript {
+    pub fn from_script(script: &[u8]) -> Self {
+        match script::classify(script) {
+            ScriptType::P2PKH => CompactScript::P2PKH(script[3..23].try_into().unwrap()),
+            ScriptType::P2SH => CompactScript::P2SH(script[2..22].try_into().unwrap()),
+            ScriptType::P2WPKH => CompactScript::P2WPKH(script[2..].try_into().unwrap()),
+            ScriptType::P2WSH => CompactScript::P2WSH(script[2..].try_into().unwrap()),
+            ScriptType::P2TR => CompactScript::P2TR(script[2..].try_into().unwrap()),
+            // Uncompressed keys are kept as they are.
+            ScriptType::P2PK if script.len() == 35 => CompactScript::P2PK(script[1..34].try_into().unwrap()),
+            _ => CompactScript::Other(script.into()),
+        }
+    }
+
+    pub fn to_script(&self) -> Vec<u8> {
+        let (prefix, payload, suffix): (&[u8], &[u8], &[u8]) = match self {
+            CompactScript::P2PKH(hash) => (&[0x76, 0xA9, 0x14], hash, &[0x88, 0xAC]),
+            CompactScript::P2SH(hash) => (&[0xA9, 0x14], hash, &[0x87]),
+            CompactScript::P2WPKH(hash) => (&[0x00, 0x14], hash, &[]),
+            CompactScript::P2WSH(hash) => (&[0x00, 0x20], hash, &[]),
+            CompactScript::P2TR(key) => (&[0x51, 0x20], key, &[]),
+            CompactScript::P2PK(key) => (&[0x21], key, &[0xAC]),
+            CompactScript::Other(script) => (&[], script, &[]),
+        };
+        [prefix, payload, suffix].concat()
+    }
+
+    pub fn address(&self) -> Option<Address> {
+        match self {
+            CompactScript::P2PKH(hash) => Some(Address::P2PKH(*hash)),
+            CompactScript::P2SH(hash) => Some(Address::P2SH(*hash)),
+            _ => None,
+        }
+    }
+
+    pub fn script_type(&self) -> usize {
+        let tag = match self {
+            CompactScript::P2PKH(_) => SCRIPT_P2PKH,
+            CompactScript::P2SH(_) => SCRIPT_P2SH,
+            CompactScript::P2WPKH(_) => SCRIPT_P2WPKH,
+            CompactScript::P2WSH(_) => SCRIPT_P2WSH,
+            CompactScript::P2TR(_) => SCRIPT_P2TR,
+            CompactScript::P2PK(_) => SCRIPT_P2PK,
+            CompactScript::Other(_) => SCRIPT_OTHER,
+        };
+        tag.into()
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            CompactScript::P2PKH(_) => 25,
+            CompactScript::P2SH(_) => 23,
+            CompactScript::P2WPKH(_) => 22,
+            CompactScript::P2WSH(_) | CompactScript::P2TR(_) => 34,
+            CompactScript::P2PK(_) => 35,
+            CompactScript::Other(script) => script.len(),
+        }
+    }
+
+    pub fn from_reader(reader: &mut HashingBufferReader) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let script = match reader.read_u8(&mut None).field("script tag")? {
+            SCRIPT_P2PKH => CompactScript::P2PKH(reader.read_buffer(20, &mut None).field("script")?.try_into().unwrap()),
+            SCRIPT_P2SH => CompactScript::P2SH(reader.read_buffer(20, &mut None).field("script")?.try_into().unwrap()),
+            SCRIPT_P2WPKH => CompactScript::P2WPKH(reader.read_buffer(20, &mut None).field("script")?.try_into().unwrap()),
+            SCRIPT_P2WSH => CompactScript::P2WSH(reader.read_buffer(32, &mut None).field("script")?.try_into().unwrap()),
+            SCRIPT_P2TR => CompactScript::P2TR(reader.read_buffer(32, &mut None).field("script")?.try_into().unwrap()),
+            SCRIPT_P2PK => CompactScript::P2PK(reader.read_buffer(33, &mut None).field("script")?.try_into().unwrap()),
+            SCRIPT_OTHER => {
+                let length = reader.read_b128_var_int_as(&mut None).field("script length")?;
+                CompactScript::Other(reader.read_buffer(length, &mut None).field("script")?.into())
+            }
+            tag => return Err(ParseError::new(offset, "script tag", ParseErrorKind::InvalidValue(tag.into()))),
+        };
+        Ok(script)
+    }
+
+    /// Moves the reader past a script written by `to_writer` without decoding it.
+    pub fn skip(reader: &mut HashingBufferReader) -> ParseResult<()> {
+        let offset = reader.offset();
+        let length = match reader.read_u8(&mut None).field("script tag")? {
+            SCRIPT_P2PKH | SCRIPT_P2SH | SCRIPT_P2WPKH => 20,
+            SCRIPT_P2WSH | SCRIPT_P2TR => 32,
+            SCRIPT_P2PK => 33,
+            SCRIPT_OTHER => reader.read_b128_var_int_as(&mut None).field("script length")?,
+            tag => return Err(ParseError::new(offset, "script tag", ParseErrorKind::InvalidValue(tag.into()))),
+        };
+        reader.skip(length, &mut None).field("script")
+    }
+
+    pub fn to_writer(&self, writer: &mut BufferWriter) {
+        let (tag, payload): (u8, &[u8]) = match self {
+            CompactScript::P2PKH(hash) => (SCRIPT_P2PKH, hash),
+            CompactScript::P2SH(hash) => (SCRIPT_P2SH, hash),
+            CompactScript::P2WPKH(hash) => (SCRIPT_P2WPKH, hash),
+            CompactScript::P2WSH(hash) => (SCRIPT_P2WSH, hash),
+            CompactScript::P2TR(key) => (SCRIPT_P2TR, key),
+            CompactScript::P2PK(key) => (SCRIPT_P2PK, key),
+            CompactScript::Other(script) => (SCRIPT_OTHER, script),
+        };
+        writer.write_u8(tag);
+        if tag == SCRIPT_OTHER {
+            writer.write_b128_var_int(payload.len().try_into().unwrap());
+        }
+        writer.write_buffer(payload);
+    }
+}
+
+pub fn confirmations(height: u32, tip_height: u32) -> u32 {
+    match height {
+        MEMPOOL_HEIGHT => 0,
+        height => tip_height + 1 - height,
+    }
+}
+
+/// Unspent output, with the same fields as a coin of Bitcoin Core's UTXO set.
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct Coin {
+    pub value: u64,
+    pub height: u32,
+    pub coinbase: bool,
+    pub script: CompactScript,
+}
+
+impl Coin {
+    pub fn address(&self) -> Option<Address> {
+        self.script.address()
+    }
+
+    /// Number of confirmations of the coin when the last block is at `tip_height`, 0 for a mempool coin.
+    pub fn confirmations(&self, tip_height: u32) -> u32 {
+        confirmations(self.height, tip_height)
+    }
+
+    /// Whether the coin can be spent by a transaction of the next block.
+    pub fn is_mature(&self, tip_height: u32) -> bool {
+        !self.coinbase || self.confirmations(tip_height) >= COINBASE_MATURITY
+    }
+
+    pub fn code(&self) -> u32 {
+        self.height * 2 + self.coinbase as u32
+    }
+
+    /// Bitcoin Core never adds the outputs of the genesis block nor outputs whose script is too large to be spent to its UTXO set.
+    pub fn is_in_utxo_set(&self) -> bool {
+        self.height > 0 && self.script.size() as u64 <= MAX_SCRIPT_SIZE
+    }
+
+    /// Writes the outpoint and coin serialization hashed by `gettxoutsetinfo` (`TxOutSer` in Bitcoin Core).
+    pub fn write_hash_data(&self, txo: &TransactionOutput, writer: &mut BufferWriter) {
+        let script = self.script.to_script();
+        writer.write_hash(txo.hash);
+        writer.write_u32(txo.index);
+        writer.write_u32(self.code());
+        writer.write_u64(self.value);
+        writer.write_var_int_le(script.len().try_into().unwrap());
+        writer.write_buffer(&script);
+    }
+}
+
+/// Input of a transaction spending a coin.
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct Spender {
+    pub hash: [u8; 32],
+    pub index: u32,
+    pub height: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Coin, CompactScript, MEMPOOL_HEIGHT};
+    use crate::{hex, BufferWriter, HashingBufferReader};
+
+    fn round_trip(script: &str) -> CompactScript {
+        let compact_script = CompactScript::from_script(&hex::decode(script).unwrap());
+        assert_eq!(hex::encode(compact_script.to_script()), script);
+        assert_eq!(compact_script.size() * 2, script.len());
+        let mut writer = BufferWriter::new();
+        compact_script.to_writer(&mut writer);
+        let buffer = writer.buffer();
+        assert_eq!(
+            CompactScript::from_reader(&mut HashingBufferReader::new(&buffer)),
+            Ok(compact_script.clone())
+        );
+        let mut reader = HashingBufferReader::new(&buffer);
+        CompactScript::skip(&mut reader).unwrap();
+        assert!(reader.is_at_end());
+        compact_script
+    }
+
+    #[test]
+    fn from_script() {
+        assert!(matches!(
+            round_trip("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"),
+            CompactScript::P2PKH(_)
+        ));
+        assert!(matches!(
+            round_trip("a914e9c3dd0c07aac76179ebc76a6c78d4d67c6c160a87"),
+            CompactScript::P2SH(_)
+        ));
+        assert!(matches!(
+            round_trip("0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            CompactScript::P2WPKH(_)
+        ));
+        assert!(matches!(
+            round_trip("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262"),
+            CompactScript::P2WSH(_)
+        ));
+        assert!(matches!(
+            round_trip("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"),
+            CompactScript::P2TR(_)
+        ));
+        assert!(matches!(
+            round_trip("2103c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5ac"),
+            CompactScript::P2PK(_)
+        ));
+        assert!(matches!(round_trip("6a0568656c6c6f"), CompactScript::Other(_)));
+        assert!(matches!(round_trip(""), CompactScript::Other(_)));
+    }
+
+    fn coin(height: u32, coinbase: bool) -> Coin {
+        Coin {
+            value: 5_000_000_000,
+            height,
+            coinbase,
+            script: CompactScript::Other(Box::new([])),
+        }
+    }
+
+    #[test]
+    fn is_mature() {
+        assert_eq!(coin(100, true).confirmations(100), 1);
+        assert_eq!(coin(MEMPOOL_HEIGHT, false).confirmations(100), 0);
+        assert!(!coin(100, true).is_mature(198));
+        assert!(coin(100, true).is_mature(199));
+        assert!(coin(100, false).is_mature(100));
+        assert!(coin(MEMPOOL_HEIGHT, false).is_mature(100));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{
+    compression::{compress_amount, decompress_amount},
+    BufferWriter, HashingBufferReader, ParseResult, ParseResultExt, TransactionOutput,
+};
+
+/// Output of an address spent by a transaction.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SentOutput {
+    pub txo: TransactionOutput,
+    /// Index of the input of the transaction spending the output.
+    pub input_index: u32,
+    pub value: u64,
+}
+
+/// Transaction that funded or spent outputs of an address, with the outputs of the address it funded and spent.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct HistoryEntry {
+    pub hash: [u8; 32],
+    pub height: u32,
+    /// Indexes of the outputs of the transaction funding the address, with their value.
+    pub received: Vec<(u32, u64)>,
+    pub sent: Vec<SentOutput>,
+}
+
+impl HistoryEntry {
+    pub fn new(hash: [u8; 32], height: u32) -> Self {
+        Self {
+            hash,
+            height,
+            received: Vec::new(),
+            sent: Vec::new(),
+        }
+    }
+
+    pub fn received_value(&self) -> u64 {
+        self.received.iter().map(|(_, value)| value).sum()
+    }
+
+    pub fn sent_value(&self) -> u64 {
+        self.sent.iter().map(|output| output.value).sum()
+    }
+
+    /// Whether the transaction sent more than it received, with the difference.
+    pub fn net_value(&self) -> (bool, u64) {
+        let (received, sent) = (self.received_value(), self.sent_value());
+        match sent > received {
+            true => (true, sent - received),
+            false => (false, received - sent),
+        }
+    }
+
+    pub fn from_reader(reader: &mut HashingBufferReader) -> ParseResult<Self> {
+        let hash = reader.read_hash(&mut None)?;
+        let height = reader.read_b128_var_int_as(&mut None).field("height")?;
+        let mut entry = Self::new(hash, height);
+        let count: usize = reader.read_b128_var_int_as(&mut None).field("received count")?;
+        for _ in 0..count {
+            let index = reader.read_b128_var_int_as(&mut None).field("output index")?;
+            let value = decompress_amount(reader.read_b128_var_int(&mut None).field("value")?);
+            entry.received.push((index, value));
+        }
+        let count: usize = reader.read_b128_var_int_as(&mut None).field("sent count")?;
+        for _ in 0..count {
+            let hash = reader.read_hash(&mut None).field("spent hash")?;
+            let index = reader.read_b128_var_int_as(&mut None).field("spent index")?;
+            let input_index = reader.read_b128_var_int_as(&mut None).field("input index")?;
+            let value = decompress_amount(reader.read_b128_var_int(&mut None).field("value")?);
+            entry.sent.push(SentOutput {
+                txo: TransactionOutput::new(hash, index),
+                input_index,
+                value,
+            });
+        }
+        Ok(entry)
+    }
+
+    pub fn to_writer(&self, writer: &mut BufferWriter) {
+        writer.write_hash(self.hash);
+        writer.write_b128_var_int(self.height.into());
+        writer.write_b128_var_int(self.received.len().try_into().unwrap());
+        for (index, value) in &self.received {
+            writer.write_b128_var_int((*index).into());
+            writer.write_b128_var_int(compress_amount(*value));
+        }
+        writer.write_b128_var_int(self.sent.len().try_into().unwrap());
+        for output in &self.sent {
+            writer.write_hash(output.txo.hash);
+            writer.write_b128_var_int(output.txo.index.into());
+            writer.write_b128_var_int(output.input_index.into());
+            writer.write_b128_var_int(compress_amount(output.value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryEntry, SentOutput};
+    use crate::{BufferWriter, HashingBufferReader, TransactionOutput};
+
+    fn round_trip(entry: HistoryEntry) {
+        let mut writer = BufferWriter::new();
+        entry.to_writer(&mut writer);
+        let buffer = writer.buffer();
+        let mut reader = HashingBufferReader::new(&buffer);
+        assert_eq!(HistoryEntry::from_reader(&mut reader), Ok(entry));
+        assert_eq!(reader.offset(), buffer.len());
+    }
+
+    #[test]
+    fn from_reader() {
+        let mut entry = HistoryEntry::new([1; 32], 170);
+        entry.received.push((3, 1_000_000_000));
+        round_trip(entry);
+        let mut entry = HistoryEntry::new([3; 32], 800_000);
+        entry.sent.push(SentOutput {
+            txo: TransactionOutput::new([2; 32], 0),
+            input_index: 1,
+            value: 12_345,
+        });
+        entry.received.push((0, 1_000));
+        entry.received.push((2, 2_000));
+        round_trip(entry);
+    }
+
+    #[test]
+    fn net_value() {
+        let mut entry = HistoryEntry::new([1; 32], 170);
+        entry.received.push((0, 1_000));
+        assert_eq!(entry.net_value(), (false, 1_000));
+        entry.sent.push(SentOutput {
+            txo: TransactionOutput::new([2; 32], 0),
+            input_index: 0,
+            value: 5_000,
+        });
+        assert_eq!(entry.net_value(), (true, 4_000));
+    }
+}
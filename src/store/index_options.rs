@@ -0,0 +1,23 @@
+/// Optional indexes maintained by the indexed backend on top of the UTXO set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexOptions {
+    pub history: bool,
+    pub spends: bool,
+    pub stats: bool,
+    pub op_returns: bool,
+}
+
+impl IndexOptions {
+    pub fn none() -> Self {
+        Self {
+            history: false,
+            spends: false,
+            stats: false,
+            op_returns: false,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.history || self.spends || self.stats || self.op_returns
+    }
+}
@@ -1,8 +1,19 @@
+mod address_stats;
+mod coin;
+mod history;
+mod index_options;
+mod op_return;
 mod store;
 mod transaction_store;
 mod transaction_store_backend;
 mod transaction_store_backend_trait;
+mod utxo_set_stats;
 
+pub use self::address_stats::AddressStats;
+pub use self::coin::{confirmations, Coin, CompactScript, Spender, MEMPOOL_HEIGHT, SCRIPT_TYPES};
+pub use self::history::{HistoryEntry, SentOutput};
+pub use self::index_options::IndexOptions;
+pub use self::op_return::{OpReturn, OpReturnIndex};
 pub use self::store::{AugmentedStore, IndexedStore, IntermediaryStore, Store};
 pub use self::transaction_store::{
     AugmentedTransactionStore, IndexedTransactionStore, IntermediaryTransactionStore, ReadonlyTransactionStore, TransactionStore,
@@ -12,3 +23,4 @@ pub use self::transaction_store_backend::{
     ReadonlyAugmentedTransactionStoreBackend, TransactionStoreAugmentation,
 };
 pub use self::transaction_store_backend_trait::{ReadonlyTransactionStoreBackendTrait, TransactionStoreBackendTrait};
+pub use self::utxo_set_stats::{OutputsStats, UtxoSetStats};
@@ -0,0 +1,286 @@
+use crate::{
+    script::{Instruction, Instructions},
+    BufferWriter, ChunkedVec, HashingBufferReader, ParseResult, ParseResultExt, RandomShardedHashMap, TransactionOutput, TryInto,
+};
+use sha2::{Digest, Sha256};
+use std::hash::Hash;
+
+/// Number of leading payload bytes by which OP_RETURN outputs are grouped, enough for the usual protocol tags.
+const PREFIX_SIZE: usize = 4;
+
+/// Output starting with OP_RETURN, with the data it carries.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct OpReturn {
+    pub txo: TransactionOutput,
+    pub height: u32,
+    pub payload: Box<[u8]>,
+}
+
+impl OpReturn {
+    pub fn new(txo: TransactionOutput, height: u32, script: &[u8]) -> Self {
+        Self {
+            txo,
+            height,
+            payload: payload(script),
+        }
+    }
+
+    /// Single SHA256 of the payload.
+    pub fn payload_hash(&self) -> [u8; 32] {
+        Sha256::digest(&self.payload).into()
+    }
+
+    pub fn from_reader(reader: &mut HashingBufferReader) -> ParseResult<Self> {
+        let hash = reader.read_hash(&mut None)?;
+        let index = reader.read_b128_var_int_as(&mut None).field("output index")?;
+        let height = reader.read_b128_var_int_as(&mut None).field("height")?;
+        let length = reader.read_b128_var_int_as(&mut None).field("payload length")?;
+        let payload = reader.read_buffer(length, &mut None).field("payload")?.into();
+        Ok(Self {
+            txo: TransactionOutput::new(hash, index),
+            height,
+            payload,
+        })
+    }
+
+    pub fn to_writer(&self, writer: &mut BufferWriter) {
+        writer.write_hash(self.txo.hash);
+        writer.write_b128_var_int(self.txo.index.into());
+        writer.write_b128_var_int(self.height.into());
+        writer.write_b128_var_int(self.payload.len().try_into().unwrap());
+        writer.write_buffer(&self.payload);
+    }
+}
+
+/// Data pushes following OP_RETURN, concatenated, or every byte following it when the script has other opcodes or ends in
+/// the middle of a push.
+fn payload(script: &[u8]) -> Box<[u8]> {
+    let script = &script[1..];
+    let mut payload = Vec::new();
+    for instruction in Instructions::new(script) {
+        match instruction {
+            Ok(Instruction::Push(_, data)) => payload.extend_from_slice(data),
+            _ => return script.into(),
+        }
+    }
+    payload.into()
+}
+
+/// OP_RETURN outputs, oldest first, with their positions grouped by each of the first bytes of their payload (the first
+/// byte, the first two bytes, and so on up to `PREFIX_SIZE` bytes) and by payload hash. Positions are appended in order, so
+/// each group stays sorted.
+#[derive(Debug, Clone, Default)]
+pub struct OpReturnIndex {
+    entries: ChunkedVec<OpReturn>,
+    prefixes: RandomShardedHashMap<Box<[u8]>, ChunkedVec<u32>>,
+    hashes: RandomShardedHashMap<[u8; 32], ChunkedVec<u32>>,
+}
+
+impl OpReturnIndex {
+    pub fn new() -> Self {
+        Self {
+            entries: ChunkedVec::new(),
+            prefixes: RandomShardedHashMap::new(),
+            hashes: RandomShardedHashMap::new(),
+        }
+    }
+
+    /// Index whose groups are sharded, for the OP_RETURN outputs of a large store.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            entries: ChunkedVec::new(),
+            prefixes: RandomShardedHashMap::with_shards(shards),
+            hashes: RandomShardedHashMap::with_shards(shards),
+        }
+    }
+
+    /// Index sharded for `capacity` entries with distinct payloads.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let hashes = RandomShardedHashMap::with_capacity(capacity);
+        Self {
+            entries: ChunkedVec::new(),
+            prefixes: RandomShardedHashMap::with_shards(hashes.shards()),
+            hashes,
+        }
+    }
+
+    pub fn entries(&self) -> &ChunkedVec<OpReturn> {
+        &self.entries
+    }
+
+    pub fn add(&mut self, entry: OpReturn) {
+        let position: u32 = self.entries.len().try_into().unwrap();
+        for length in 1..=entry.payload.len().min(PREFIX_SIZE) {
+            let prefix = &entry.payload[..length];
+            match self.prefixes.get_mut(prefix) {
+                Some(positions) => positions.push(position),
+                None => {
+                    self.prefixes.insert(prefix.into(), [position].into_iter().collect());
+                }
+            }
+        }
+        let hash = entry.payload_hash();
+        match self.hashes.get_mut(&hash) {
+            Some(positions) => positions.push(position),
+            None => {
+                self.hashes.insert(hash, [position].into_iter().collect());
+            }
+        }
+        self.entries.push(entry);
+    }
+
+    /// Keeps the entries for which `keep` returns true, in the same order, renumbering their positions in the groups.
+    pub fn retain(&mut self, keep: impl Fn(&OpReturn) -> bool) {
+        let mut positions = Vec::with_capacity(self.entries.len());
+        let mut entries = ChunkedVec::new();
+        for entry in self.entries.iter() {
+            match keep(entry) {
+                true => {
+                    positions.push(Some(entries.len().try_into().unwrap()));
+                    entries.push(entry.clone());
+                }
+                false => positions.push(None),
+            }
+        }
+        if entries.len() < self.entries.len() {
+            self.entries = entries;
+            self.prefixes = renumber(&self.prefixes, &positions);
+            self.hashes = renumber(&self.hashes, &positions);
+        }
+    }
+
+    /// Iterates over at most `limit` entries whose payload starts with `prefix`, newest first, after skipping `offset` ones,
+    /// and returns the number of entries whose payload starts with `prefix`.
+    pub fn iterate_prefix(&self, prefix: &[u8], offset: usize, limit: usize, mut callback: impl FnMut(&OpReturn)) -> usize {
+        let positions = match self.prefixes.get(&prefix[..prefix.len().min(PREFIX_SIZE)]) {
+            Some(positions) => positions,
+            None => return 0,
+        };
+        if prefix.len() <= PREFIX_SIZE {
+            for position in positions.iter_rev(offset).take(limit) {
+                callback(&self.entries[*position as usize]);
+            }
+            return positions.len();
+        }
+        let mut count = 0;
+        for position in positions.iter_rev(0) {
+            let entry = &self.entries[*position as usize];
+            if entry.payload.starts_with(prefix) {
+                if count >= offset && count - offset < limit {
+                    callback(entry);
+                }
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Iterates over the entries whose payload has the SHA256 `hash`, newest first.
+    pub fn iterate_hash(&self, hash: &[u8; 32], mut callback: impl FnMut(&OpReturn)) {
+        if let Some(positions) = self.hashes.get(hash) {
+            for position in positions.iter_rev(0) {
+                callback(&self.entries[*position as usize]);
+            }
+        }
+    }
+}
+
+/// Groups with the positions of the kept entries replaced by their new positions, dropping the removed entries and the
+/// groups left empty.
+fn renumber<K: Eq + Hash + Clone>(
+    groups: &RandomShardedHashMap<K, ChunkedVec<u32>>,
+    positions: &[Option<u32>],
+) -> RandomShardedHashMap<K, ChunkedVec<u32>> {
+    let mut renumbered = RandomShardedHashMap::with_shards(groups.shards());
+    for (key, group) in groups {
+        let group: ChunkedVec<u32> = group.iter().filter_map(|position| positions[*position as usize]).collect();
+        if !group.is_empty() {
+            renumbered.insert(key.clone(), group);
+        }
+    }
+    renumbered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpReturn, OpReturnIndex};
+    use crate::{hex, BufferWriter, HashingBufferReader, TransactionOutput};
+
+    fn op_return(index: u32, script: &str) -> OpReturn {
+        OpReturn::new(
+            TransactionOutput::new([1; 32], index),
+            800_000 + index,
+            &hex::decode(script).unwrap(),
+        )
+    }
+
+    fn indexes(index: &OpReturnIndex, prefix: &str, offset: usize, limit: usize) -> (Vec<u32>, usize) {
+        let mut indexes = Vec::new();
+        let count = index.iterate_prefix(&hex::decode(prefix).unwrap(), offset, limit, |entry| indexes.push(entry.txo.index));
+        (indexes, count)
+    }
+
+    #[test]
+    fn payload() {
+        assert_eq!(&*op_return(0, "6a0b68656c6c6f20776f726c64").payload, b"hello world");
+        assert_eq!(&*op_return(0, "6a026f6d4c03aabbcc").payload, &[0x6F, 0x6D, 0xAA, 0xBB, 0xCC]);
+        assert!(op_return(0, "6a").payload.is_empty());
+        assert_eq!(&*op_return(0, "6a51aa").payload, &[0x51, 0xAA]);
+        assert_eq!(&*op_return(0, "6a0568656c").payload, &[0x05, 0x68, 0x65, 0x6C]);
+    }
+
+    #[test]
+    fn from_reader() {
+        let entry = op_return(3, "6a0b68656c6c6f20776f726c64");
+        let mut writer = BufferWriter::new();
+        entry.to_writer(&mut writer);
+        let buffer = writer.buffer();
+        let mut reader = HashingBufferReader::new(&buffer);
+        assert_eq!(OpReturn::from_reader(&mut reader), Ok(entry));
+        assert!(reader.is_at_end());
+    }
+
+    #[test]
+    fn iterate() {
+        let mut index = OpReturnIndex::new();
+        for (position, script) in ["066f6d6e69aabb", "046f6d6e69", "026f6d", "066f6d6e6fccdd", "0100"]
+            .iter()
+            .enumerate()
+        {
+            index.add(op_return(position as u32, &format!("6a{}", script)));
+        }
+        assert_eq!(indexes(&index, "6f6d6e69", 0, 10), (vec![1, 0], 2));
+        assert_eq!(indexes(&index, "6f6d6e69aa", 0, 10), (vec![0], 1));
+        assert_eq!(indexes(&index, "6f6d6e69aabb", 1, 10), (vec![], 1));
+        assert_eq!(indexes(&index, "6f6d6e6fccdd", 0, 10), (vec![3], 1));
+        assert_eq!(indexes(&index, "6f6d", 0, 10), (vec![3, 2, 1, 0], 4));
+        assert_eq!(indexes(&index, "6f6d", 1, 2), (vec![2, 1], 4));
+        assert_eq!(indexes(&index, "6f", 3, 10), (vec![0], 4));
+        assert_eq!(indexes(&index, "01", 0, 10), (vec![], 0));
+        let hash = op_return(0, "6a026f6d").payload_hash();
+        let mut found = Vec::new();
+        index.iterate_hash(&hash, |entry| found.push(entry.txo.index));
+        assert_eq!(found, vec![2]);
+    }
+
+    #[test]
+    fn retain() {
+        let mut index = OpReturnIndex::new();
+        for (position, script) in ["066f6d6e69aabb", "046f6d6e69", "026f6d", "066f6d6e6fccdd"].iter().enumerate() {
+            index.add(op_return(position as u32, &format!("6a{}", script)));
+        }
+        let clone = index.clone();
+        index.retain(|entry| entry.txo.index % 2 == 1);
+        assert_eq!(indexes(&index, "6f6d", 0, 10), (vec![3, 1], 2));
+        assert_eq!(indexes(&index, "6f6d6e69", 0, 10), (vec![1], 1));
+        assert_eq!(indexes(&index, "6f6d6e69aa", 0, 10), (vec![], 0));
+        assert_eq!(indexes(&clone, "6f6d", 0, 10), (vec![3, 2, 1, 0], 4));
+        let mut found = Vec::new();
+        index.iterate_hash(&op_return(0, "6a066f6d6e6fccdd").payload_hash(), |entry| {
+            found.push(entry.txo.index)
+        });
+        assert_eq!(found, vec![3]);
+        index.iterate_hash(&op_return(0, "6a026f6d").payload_hash(), |entry| found.push(entry.txo.index));
+        assert_eq!(found, vec![3]);
+    }
+}
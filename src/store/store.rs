@@ -1,10 +1,13 @@
 use super::{
-    AugmentedTransactionStore, AugmentedTransactionStoreBackend, IndexedTransactionStore, IndexedTransactionStoreBackend,
+    AugmentedTransactionStore, AugmentedTransactionStoreBackend, IndexOptions, IndexedTransactionStore, IndexedTransactionStoreBackend,
     IntermediaryTransactionStore, IntermediaryTransactionStoreBackend, TransactionStore, TransactionStoreBackendTrait,
 };
-use crate::{Batcher, BlockHeader, BlockTrait, BufferWriter, HashingBufferReader, Logger, PartialLogger, SequentialThreadPool, TryInto};
+use crate::{
+    fees::TransactionFee, reverse_hex, snapshot::SnapshotReader, Batcher, BlockHeader, BlockTrait, BufferWriter, HashingBufferReader,
+    Logger, ParseResult, ParseResultExt, PartialLogger, SequentialThreadPool, TryInto,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Store<T: TransactionStoreBackendTrait> {
     transaction_store: TransactionStore<T>,
     block_headers: Vec<BlockHeader>,
@@ -38,6 +41,20 @@ impl<T: TransactionStoreBackendTrait> Store<T> {
         }
     }
 
+    /// Heights and hashes of the blocks whose time is unknown, as store files did not contain block times before version 3.
+    pub fn blocks_without_time(&self) -> Vec<(usize, [u8; 32])> {
+        self.block_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, block_header)| block_header.time == 0)
+            .map(|(height, block_header)| (height, block_header.hash))
+            .collect()
+    }
+
+    pub fn set_block_time(&mut self, height: usize, time: u32) {
+        self.block_headers[height].time = time;
+    }
+
     fn add_block_header(&mut self, block_header: BlockHeader) {
         if let Some(last_block_header) = self.block_headers.last() {
             assert!(last_block_header.hash == block_header.previous_block_hash);
@@ -49,10 +66,20 @@ impl<T: TransactionStoreBackendTrait> Store<T> {
         self.add_block_header(block.header().clone());
         self.transaction_store.add_block(block);
     }
+
+    pub fn add_block_with_fees(&mut self, block: &impl BlockTrait) -> Vec<([u8; 32], TransactionFee)> {
+        self.add_block_header(block.header().clone());
+        self.transaction_store.add_block_with_fees(block)
+    }
 }
 
 pub type IndexedStore = Store<IndexedTransactionStoreBackend>;
 
+const STORE_FILE_MAGIC: &[u8; 4] = b"BIDX";
+const STORE_FILE_VERSION: u32 = 3;
+/// Last store file version without block times, which are requested from the node after loading it.
+const STORE_FILE_VERSION_WITHOUT_TIMES: u32 = 2;
+
 fn process_blocks(store: &mut Store<impl TransactionStoreBackendTrait>, blocks: &[impl BlockTrait], logger: Logger) {
     let mut logger = PartialLogger::new(1000, &logger);
     for block in blocks {
@@ -67,10 +94,58 @@ impl IndexedStore {
         blocks: Vec<T>,
         threads: usize,
         batch_size: usize,
+        options: &IndexOptions,
+        logger: Logger,
+    ) -> Self {
+        let mut store = Self::large(options);
+        store.add_blocks(blocks, threads, batch_size, logger);
+        store
+    }
+
+    pub fn from_snapshot<T: 'static + BlockTrait + Clone + Send + Sync>(
+        path: &str,
+        mut blocks: Vec<T>,
+        threads: usize,
+        batch_size: usize,
+        options: &IndexOptions,
         logger: Logger,
     ) -> Self {
-        let mut store = Self::large();
-        if threads > 1 {
+        logger.log("importing snapshot...");
+        let snapshot = SnapshotReader::new(path);
+        let base_block_hash = snapshot.base_block_hash();
+        let count = match blocks.iter().position(|block| block.hash() == base_block_hash) {
+            Some(height) => height + 1,
+            None => panic!(
+                "Snapshot base block {} not found in block files",
+                reverse_hex::encode(base_block_hash)
+            ),
+        };
+        let mut store = Self::large(options);
+        for block in blocks.drain(..count) {
+            store.add_block_header(block.header().clone());
+        }
+        let mut partial_logger = PartialLogger::new(1000000, &logger);
+        let coins_count = snapshot.coins_count();
+        snapshot.coins(|txo, coin| {
+            partial_logger.log(|index| format!("importing utxo {} of {} from snapshot...", index, coins_count));
+            store
+                .transaction_store
+                .add_transaction_output(txo, &coin.output, coin.height, coin.coinbase);
+        });
+        logger.log("importing snapshot done!");
+        store.add_blocks(blocks, threads, batch_size, logger);
+        store
+    }
+
+    fn add_blocks<T: 'static + BlockTrait + Clone + Send + Sync>(
+        &mut self,
+        blocks: Vec<T>,
+        threads: usize,
+        batch_size: usize,
+        logger: Logger,
+    ) {
+        // Intermediary stores only keep the outputs left unspent by their batch, which is not enough to build the indexes.
+        if threads > 1 && !self.backend().index_options().any() {
             let batcher = Batcher::new(blocks, batch_size);
             let threadpool = SequentialThreadPool::new(
                 threads,
@@ -87,7 +162,7 @@ impl IndexedStore {
                     blocks[0].height(),
                     blocks[blocks.len() - 1].height(),
                 ));
-                intermediary_store.merge(&mut store);
+                intermediary_store.merge(self);
                 logger.log(format!(
                     "merging blocks {} - {} done!",
                     blocks[0].height(),
@@ -95,60 +170,102 @@ impl IndexedStore {
                 ));
             }
         } else {
-            process_blocks(&mut store, &blocks, logger);
+            process_blocks(self, &blocks, logger);
         }
-        store
     }
 
-    pub fn from_file(path: &str, logger: &Logger) -> Option<Self> {
+    pub fn from_file(path: &str, threads: usize, options: &IndexOptions, logger: &Logger) -> Option<Self> {
         logger.log("reading store from file...");
         let store = match std::fs::read(path) {
-            Ok(buffer) => {
-                let mut reader = HashingBufferReader::new(&buffer);
-                Some(Self::from_reader(&mut reader, logger))
-            }
+            Ok(buffer) => Self::from_buffer(&buffer, threads, options, logger),
             Err(_) => None,
         };
         logger.log("reading store from file done!");
         store
     }
 
-    fn from_reader(reader: &mut HashingBufferReader, logger: &Logger) -> Self {
+    pub fn from_buffer(buffer: &[u8], threads: usize, options: &IndexOptions, logger: &Logger) -> Option<Self> {
+        let mut reader = HashingBufferReader::new(buffer);
+        if !buffer.starts_with(STORE_FILE_MAGIC) {
+            logger.log("store file has the legacy format without coin heights and scripts");
+            return None;
+        }
+        reader.skip(STORE_FILE_MAGIC.len(), &mut None).unwrap();
+        match Self::from_reader(&mut reader, threads, options, logger) {
+            Ok(store) => store,
+            Err(error) => {
+                logger.log(format!("store file is corrupted, {}", error));
+                None
+            }
+        }
+    }
+
+    fn from_reader(reader: &mut HashingBufferReader, threads: usize, options: &IndexOptions, logger: &Logger) -> ParseResult<Option<Self>> {
+        let version = reader.read_u32_le(&mut None).field("version")?;
+        if version != STORE_FILE_VERSION && version != STORE_FILE_VERSION_WITHOUT_TIMES {
+            match version < STORE_FILE_VERSION_WITHOUT_TIMES {
+                true => logger.log(format!("store file version {} does not contain coin heights and scripts", version)),
+                false => logger.log(format!("unsupported store file version {}", version)),
+            }
+            return Ok(None);
+        }
         let mut block_headers = Vec::new();
-        for _ in 0..reader.read_u32_le(&mut None) {
-            let hash = reader.read_hash(&mut None);
-            let previous_block_hash = reader.read_hash(&mut None);
-            block_headers.push(BlockHeader { hash, previous_block_hash });
+        for _ in 0..reader.read_u32_le(&mut None).field("block header count")? {
+            let hash = reader.read_hash(&mut None).field("block hash")?;
+            let previous_block_hash = reader.read_hash(&mut None).field("previous block hash")?;
+            let time = match version {
+                STORE_FILE_VERSION_WITHOUT_TIMES => 0,
+                _ => reader.read_u32_le(&mut None).field("time")?,
+            };
+            block_headers.push(BlockHeader {
+                hash,
+                previous_block_hash,
+                time,
+            });
         }
-        Self {
-            block_headers,
-            transaction_store: IndexedTransactionStore::from_reader(reader, logger),
+        if version == STORE_FILE_VERSION_WITHOUT_TIMES {
+            logger.log(format!("store file version {} does not contain block times", version));
         }
+        let Some(transaction_store) = IndexedTransactionStore::from_reader(reader, threads, options, logger)? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            block_headers,
+            transaction_store,
+        }))
     }
 
     fn to_writer(&self, writer: &mut BufferWriter, logger: &Logger) {
+        writer.write_buffer(STORE_FILE_MAGIC);
+        writer.write_u32(STORE_FILE_VERSION);
         writer.write_u32(self.block_headers().len().try_into().unwrap());
         for block_header in self.block_headers().iter() {
             writer.write_buffer(&block_header.hash);
             writer.write_buffer(&block_header.previous_block_hash);
+            writer.write_u32(block_header.time);
         }
         self.backend().to_writer(writer, logger);
     }
 
-    pub fn to_file(&self, path: &str, logger: &Logger) {
-        logger.log("writing store to file...");
+    pub fn to_buffer(&self, logger: &Logger) -> Vec<u8> {
         let mut writer = BufferWriter::new();
         self.to_writer(&mut writer, logger);
+        writer.buffer()
+    }
+
+    pub fn to_file(&self, path: &str, logger: &Logger) {
+        logger.log("writing store to file...");
+        let buffer = self.to_buffer(logger);
         logger.log("writing buffer to file...");
-        std::fs::write(path, writer.buffer()).unwrap();
+        std::fs::write(path, buffer).unwrap();
         logger.log("writing buffer to file done!");
         logger.log("writing store to file done!");
     }
 
-    pub fn large() -> Self {
+    pub fn large(options: &IndexOptions) -> Self {
         Self {
             block_headers: Vec::new(),
-            transaction_store: IndexedTransactionStore::large(),
+            transaction_store: IndexedTransactionStore::large(options),
         }
     }
 }
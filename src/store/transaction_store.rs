@@ -1,21 +1,14 @@
 use super::{
-    AugmentedTransactionStoreBackend, IndexedTransactionStoreBackend, IntermediaryTransactionStoreBackend,
-    ReadonlyTransactionStoreBackendTrait, TransactionStoreAugmentation, TransactionStoreBackendTrait,
+    AddressStats, AugmentedTransactionStoreBackend, Coin, CompactScript, HistoryEntry, IndexOptions, IndexedTransactionStoreBackend,
+    IntermediaryTransactionStoreBackend, OpReturn, ReadonlyTransactionStoreBackendTrait, Spender, TransactionStoreAugmentation,
+    TransactionStoreBackendTrait,
+};
+use crate::{
+    fees::TransactionFee, script, transaction::Output, Address, BlockTrait, HashingBufferReader, Logger, ParseResult, Transaction,
+    TransactionOutput, TryInto,
 };
-use crate::{Address, BlockTrait, HashingBufferReader, Logger, Transaction, TransactionOutput, TryInto};
-
-trait ScriptExt {
-    #[allow(non_snake_case)]
-    fn starts_with_OP_RETURN(&self) -> bool;
-}
-
-impl ScriptExt for Vec<u8> {
-    fn starts_with_OP_RETURN(&self) -> bool {
-        self.len() > 0 && self[0] == 0x6a
-    }
-}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TransactionStore<T: TransactionStoreBackendTrait> {
     strict: bool,
     backend: T,
@@ -34,64 +27,102 @@ impl<T: TransactionStoreBackendTrait> TransactionStore<T> {
         self.backend
     }
 
-    fn spend_transaction_outputs(&mut self, txos: &[TransactionOutput]) {
-        for txo in txos {
-            if txo != &TransactionOutput::new([0; 32], u32::MAX) {
-                assert!(self.backend.spend_transaction_output(txo) || !self.strict);
-            }
+    fn spend_transaction_output(&mut self, txo: &TransactionOutput, spender: &Spender) {
+        if txo != &TransactionOutput::new([0; 32], u32::MAX) {
+            assert!(self.backend.spend_transaction_output(txo, spender) || !self.strict);
         }
     }
 
     pub fn can_add_transaction(&self, transaction: &Transaction) -> bool {
-        for txo in &transaction.inputs {
-            if !self.backend.has_transaction_output(txo) {
+        for input in &transaction.inputs {
+            if !self.backend.has_transaction_output(&input.txo) {
                 return false;
             }
         }
         true
     }
 
-    pub fn add_transaction(&mut self, transaction: &Transaction) {
-        self.spend_transaction_outputs(&transaction.inputs);
+    pub fn add_transaction_output(&mut self, txo: TransactionOutput, output: &Output, height: u32, coinbase: bool) {
+        if script::is_op_return(&output.script) {
+            self.backend.add_op_return(txo, height, &output.script);
+        } else {
+            let coin = Coin {
+                value: output.value,
+                height,
+                coinbase,
+                script: CompactScript::from_script(&output.script),
+            };
+            self.backend.add_transaction_output(txo, coin);
+        }
+    }
+
+    pub fn add_transaction(&mut self, transaction: &Transaction, height: u32) {
+        for (index, input) in transaction.inputs.iter().enumerate() {
+            let spender = Spender {
+                hash: transaction.hash,
+                index: index.try_into().unwrap(),
+                height,
+            };
+            self.spend_transaction_output(&input.txo, &spender);
+        }
+        let coinbase = transaction.is_coinbase();
         for (index, output) in transaction.outputs.iter().enumerate() {
-            if !output.script.starts_with_OP_RETURN() {
-                let index = index.try_into().unwrap();
-                let address = Address::from_script(&output.script).ok();
-                self.backend
-                    .add_transaction_output(TransactionOutput::new(transaction.hash, index), address, output.value);
-            }
+            let index = index.try_into().unwrap();
+            self.add_transaction_output(TransactionOutput::new(transaction.hash, index), output, height, coinbase);
         }
     }
 
     pub fn add_block(&mut self, block: &impl BlockTrait) {
+        let height = block.height().try_into().unwrap();
+        block.transactions(&mut |transaction| {
+            self.add_transaction(transaction, height);
+        });
+    }
+
+    /// Fee paid by the transaction, `None` for coinbase transactions and when an output it spends is not in the store.
+    pub fn transaction_fee(&self, transaction: &Transaction) -> Option<TransactionFee> {
+        if transaction.is_coinbase() {
+            return None;
+        }
+        let mut input_value = 0;
+        for input in &transaction.inputs {
+            input_value += self.backend.transaction_output(&input.txo)?.value;
+        }
+        TransactionFee::new(transaction, input_value)
+    }
+
+    /// Adds the block like `add_block`, also returning the fees of its transactions in the order of the block.
+    pub fn add_block_with_fees(&mut self, block: &impl BlockTrait) -> Vec<([u8; 32], TransactionFee)> {
+        let height = block.height().try_into().unwrap();
+        let mut fees = Vec::new();
         block.transactions(&mut |transaction| {
-            self.add_transaction(transaction);
+            if let Some(fee) = self.transaction_fee(transaction) {
+                fees.push((transaction.hash, fee));
+            }
+            self.add_transaction(transaction, height);
         });
+        fees
     }
 }
 
 pub type IndexedTransactionStore = TransactionStore<IndexedTransactionStoreBackend>;
 
 impl IndexedTransactionStore {
-    pub fn new(strict: bool) -> Self {
-        Self {
-            strict,
-            backend: IndexedTransactionStoreBackend::new(),
-        }
-    }
-
-    pub fn large() -> Self {
+    pub fn large(options: &IndexOptions) -> Self {
         Self {
             strict: true,
-            backend: IndexedTransactionStoreBackend::large(),
+            backend: IndexedTransactionStoreBackend::large(options),
         }
     }
 
-    pub fn from_reader(reader: &mut HashingBufferReader, logger: &Logger) -> Self {
-        Self {
-            strict: true,
-            backend: IndexedTransactionStoreBackend::from_reader(reader, logger),
-        }
+    pub fn from_reader(
+        reader: &mut HashingBufferReader,
+        threads: usize,
+        options: &IndexOptions,
+        logger: &Logger,
+    ) -> ParseResult<Option<Self>> {
+        let backend = IndexedTransactionStoreBackend::from_reader(reader, threads, options, logger)?;
+        Ok(backend.map(|backend| Self { strict: true, backend }))
     }
 }
 
@@ -106,9 +137,11 @@ impl IntermediaryTransactionStore {
     }
 
     pub fn merge<T: TransactionStoreBackendTrait>(&self, store: &mut TransactionStore<T>) {
-        store.spend_transaction_outputs(&self.backend().spent_txos());
-        for (txo, (address, value)) in self.backend().unspent_txos() {
-            store.mut_backend().add_transaction_output(txo.clone(), address.clone(), *value);
+        for (txo, spender) in self.backend().spent_txos() {
+            store.spend_transaction_output(txo, spender);
+        }
+        for (txo, coin) in self.backend().unspent_txos() {
+            store.mut_backend().add_transaction_output(txo.clone(), coin.clone());
         }
     }
 }
@@ -123,6 +156,26 @@ impl<'a, T: TransactionStoreBackendTrait> AugmentedTransactionStore<'a, T> {
         }
     }
 
+    /// Store augmenting `transaction_store` seen through `layers`.
+    pub fn with_layers(transaction_store: &'a TransactionStore<T>, layers: Vec<&'a TransactionStoreAugmentation>) -> Self {
+        Self {
+            strict: true,
+            backend: AugmentedTransactionStoreBackend::with_layers(transaction_store.backend(), layers),
+        }
+    }
+
+    /// Store that goes on from an augmentation built on `transaction_store` seen through `layers`.
+    pub fn with_augmentation(
+        transaction_store: &'a TransactionStore<T>,
+        layers: Vec<&'a TransactionStoreAugmentation>,
+        augmentation: TransactionStoreAugmentation,
+    ) -> Self {
+        Self {
+            strict: true,
+            backend: AugmentedTransactionStoreBackend::with_augmentation(transaction_store.backend(), layers, augmentation),
+        }
+    }
+
     pub fn augmentation(&self) -> &TransactionStoreAugmentation {
         &self.backend().augmentation()
     }
@@ -148,7 +201,39 @@ impl<T: ReadonlyTransactionStoreBackendTrait> ReadonlyTransactionStore<T> {
         self.backend.balance(address)
     }
 
-    pub fn iterate_transaction_outputs(&self, address: &Address, callback: impl FnMut(&TransactionOutput, u64)) {
+    pub fn mature_balance(&self, address: &Address, tip_height: u32) -> u64 {
+        self.backend.mature_balance(address, tip_height)
+    }
+
+    pub fn unspent_transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin> {
+        self.backend.unspent_transaction_output(txo)
+    }
+
+    pub fn iterate_transaction_outputs(&self, address: &Address, callback: impl FnMut(&TransactionOutput, &Coin)) {
         self.backend.iterate_transaction_outputs(address, callback);
     }
+
+    pub fn history_len(&self, address: &Address) -> usize {
+        self.backend.history_len(address)
+    }
+
+    pub fn address_stats(&self, address: &Address) -> Option<AddressStats> {
+        self.backend.address_stats(address)
+    }
+
+    pub fn spender(&self, txo: &TransactionOutput) -> Option<&Spender> {
+        self.backend.spender(txo)
+    }
+
+    pub fn iterate_history(&self, address: &Address, offset: usize, limit: usize, callback: impl FnMut(&HistoryEntry)) {
+        self.backend.iterate_history(address, offset, limit, callback);
+    }
+
+    pub fn iterate_op_returns(&self, prefix: &[u8], offset: usize, limit: usize, callback: impl FnMut(&OpReturn)) -> usize {
+        self.backend.iterate_op_returns(prefix, offset, limit, callback)
+    }
+
+    pub fn iterate_op_returns_by_hash(&self, hash: &[u8; 32], callback: impl FnMut(&OpReturn)) {
+        self.backend.iterate_op_returns_by_hash(hash, callback);
+    }
 }
@@ -1,119 +1,796 @@
-use super::{ReadonlyTransactionStoreBackendTrait, TransactionStoreBackendTrait};
+use super::{
+    AddressStats, Coin, CompactScript, HistoryEntry, IndexOptions, OpReturn, OpReturnIndex, ReadonlyTransactionStoreBackendTrait,
+    SentOutput, Spender, TransactionStoreBackendTrait, UtxoSetStats,
+};
 use crate::{
-    Address, AddressHashMap, BufferWriter, HashSet, HashingBufferReader, Logger, PartialLogger, TransactionOutput,
-    TransactionOutputHashMap, TryInto,
+    compression::{compress_amount, decompress_amount},
+    muhash::MuHash3072,
+    reverse_hex, script, Address, Arc, BufferWriter, ChunkedVec, Error, HashMap, HashSet, Hasher, HashingBufferReader, Logger, ParseError,
+    ParseErrorKind, ParseResult, ParseResultExt, PartialLogger, ShardedAddressHashMap, ShardedTransactionOutputHashMap, Transaction,
+    TransactionOutput, TransactionOutputHashMap, TryInto,
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::sync_channel,
+    },
+    thread::scope,
 };
 
-#[derive(Debug)]
+/// History entries of each address, shared between the clones of the store until the address gets a new entry.
+type History = ShardedAddressHashMap<Arc<ChunkedVec<HistoryEntry>>>;
+
+/// Store whose maps are sharded so that cloning it is cheap and adding blocks to a clone only copies the shards they modify.
+#[derive(Debug, Clone)]
 pub struct IndexedTransactionStoreBackend {
-    unspent_txo_address_map: TransactionOutputHashMap<(u64, Option<Address>)>,
-    address_unspent_txos_map: AddressHashMap<TransactionOutputHashMap<u64>>,
+    unspent_txo_address_map: ShardedTransactionOutputHashMap<Coin>,
+    address_unspent_txos_map: ShardedAddressHashMap<TransactionOutputHashMap<()>>,
+    muhash: Option<Box<MuHash3072>>,
+    history: Option<History>,
+    spends: Option<ShardedTransactionOutputHashMap<Spender>>,
+    stats: Option<ShardedAddressHashMap<AddressStats>>,
+    op_returns: Option<OpReturnIndex>,
+    utxo_set_stats: Box<UtxoSetStats>,
 }
 
+const SECTION_HISTORY: u8 = 1;
+const SECTION_SPENDS: u8 = 2;
+const SECTION_STATS: u8 = 3;
+const SECTION_OP_RETURNS: u8 = 4;
+
+/// Shards of the optional indexes of a large store, which are empty when it is created.
+const INDEX_SHARDS: usize = 1 << 20;
+
 impl IndexedTransactionStoreBackend {
-    pub fn new() -> Self {
+    pub fn new(options: &IndexOptions) -> Self {
+        Self::with_shards(options, 1)
+    }
+
+    /// Empty store whose maps have `shards` shards, without a MuHash.
+    pub fn with_shards(options: &IndexOptions, shards: usize) -> Self {
         Self {
-            unspent_txo_address_map: TransactionOutputHashMap::new(),
-            address_unspent_txos_map: AddressHashMap::new(),
+            unspent_txo_address_map: ShardedTransactionOutputHashMap::with_shards(shards),
+            address_unspent_txos_map: ShardedAddressHashMap::with_shards(shards),
+            muhash: None,
+            history: options.history.then(|| History::with_shards(shards)),
+            spends: options.spends.then(|| ShardedTransactionOutputHashMap::with_shards(shards)),
+            stats: options.stats.then(|| ShardedAddressHashMap::with_shards(shards)),
+            op_returns: options.op_returns.then(|| OpReturnIndex::with_shards(shards)),
+            utxo_set_stats: Box::new(UtxoSetStats::new()),
         }
     }
 
-    pub fn large() -> Self {
+    pub fn large(options: &IndexOptions) -> Self {
         Self {
-            unspent_txo_address_map: TransactionOutputHashMap::with_capacity(100_000_000),
-            address_unspent_txos_map: AddressHashMap::with_capacity(50_000_000),
+            unspent_txo_address_map: ShardedTransactionOutputHashMap::with_capacity(100_000_000),
+            address_unspent_txos_map: ShardedAddressHashMap::with_capacity(50_000_000),
+            muhash: Some(Box::new(MuHash3072::new())),
+            history: options.history.then(|| History::with_shards(INDEX_SHARDS)),
+            spends: options.spends.then(|| ShardedTransactionOutputHashMap::with_shards(INDEX_SHARDS)),
+            stats: options.stats.then(|| ShardedAddressHashMap::with_shards(INDEX_SHARDS)),
+            op_returns: options.op_returns.then(|| OpReturnIndex::with_shards(INDEX_SHARDS)),
+            utxo_set_stats: Box::new(UtxoSetStats::new()),
         }
     }
 
-    pub fn from_reader(reader: &mut HashingBufferReader, logger: &Logger) -> Self {
-        let mut store = Self::large();
-        let mut logger = PartialLogger::new(1000000, logger);
-        for _ in 0..reader.read_u32_le(&mut None) {
-            logger.log(|index| format!("reading utxo {} from buffer...", index));
-            let txo = TransactionOutput::new(reader.read_hash(&mut None), reader.read_u32_le(&mut None));
-            let value = reader.read_u64_le(&mut None);
-            let address = match reader.read_bool(&mut None) {
-                true => Some(Address::from_slice(reader.read_buffer(21, &mut None)).unwrap()),
-                false => None,
-            };
-            store.add_transaction_output(txo, address, value);
+    pub fn from_reader(
+        reader: &mut HashingBufferReader,
+        threads: usize,
+        options: &IndexOptions,
+        logger: &Logger,
+    ) -> ParseResult<Option<Self>> {
+        let mut store = Self::large(&IndexOptions::none());
+        let count = reader.read_u32_le(&mut None).field("utxo count")?;
+        logger.log("splitting utxos into chunks...");
+        let chunks = split_transaction_outputs(reader, count)?;
+        logger.log(format!("splitting utxos into {} chunks done!", chunks.len()));
+        // Worker threads decode the chunks and route their utxos and addresses to the threads filling the groups of shards
+        // of the maps, each group being filled by a single thread.
+        let next_chunk = AtomicUsize::new(0);
+        let IndexedTransactionStoreBackend {
+            unspent_txo_address_map,
+            address_unspent_txos_map,
+            ..
+        } = &mut store;
+        let utxo_set_stats = unspent_txo_address_map.fill_groups(threads, |txo_router, txo_groups| {
+            address_unspent_txos_map.fill_groups(threads, |address_router, address_groups| {
+                scope(|scope| {
+                    let mut txo_senders = Vec::new();
+                    for mut group in txo_groups {
+                        let (sender, receiver) = sync_channel::<Vec<(TransactionOutput, Coin)>>(threads);
+                        txo_senders.push(sender);
+                        scope.spawn(move || {
+                            for coins in receiver {
+                                for (txo, coin) in coins {
+                                    group.insert(txo, coin);
+                                }
+                            }
+                        });
+                    }
+                    let mut address_senders = Vec::new();
+                    for mut group in address_groups {
+                        let (sender, receiver) = sync_channel::<Vec<(Address, TransactionOutput)>>(threads);
+                        address_senders.push(sender);
+                        scope.spawn(move || {
+                            for addresses in receiver {
+                                for (address, txo) in addresses {
+                                    match group.get_mut(&address) {
+                                        Some(unspent_txos) => {
+                                            unspent_txos.insert(txo, ());
+                                        }
+                                        None => {
+                                            let mut unspent_txos = TransactionOutputHashMap::new();
+                                            unspent_txos.insert(txo, ());
+                                            group.insert(address, unspent_txos);
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    }
+                    let decoders: Vec<_> = (0..threads)
+                        .map(|_| {
+                            let (chunks, next_chunk, txo_router, address_router) = (&chunks, &next_chunk, &txo_router, &address_router);
+                            let (txo_senders, address_senders) = (txo_senders.clone(), address_senders.clone());
+                            scope.spawn(move || -> ParseResult<UtxoSetStats> {
+                                let mut utxo_set_stats = UtxoSetStats::new();
+                                loop {
+                                    let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+                                    let Some(chunk) = chunks.get(index) else {
+                                        break;
+                                    };
+                                    logger.log(format!("reading utxo chunk {} of {} from buffer...", index + 1, chunks.len()));
+                                    let mut coins: Vec<_> = txo_senders.iter().map(|_| Vec::new()).collect();
+                                    let mut addresses: Vec<_> = address_senders.iter().map(|_| Vec::new()).collect();
+                                    for (txo, coin) in read_transaction_outputs(chunk)? {
+                                        utxo_set_stats.add(&coin);
+                                        if let Some(address) = coin.address() {
+                                            addresses[address_router.group(&address)].push((address, txo.clone()));
+                                        }
+                                        coins[txo_router.group(&txo)].push((txo, coin));
+                                    }
+                                    for (sender, coins) in txo_senders.iter().zip(coins) {
+                                        sender.send(coins).unwrap();
+                                    }
+                                    for (sender, addresses) in address_senders.iter().zip(addresses) {
+                                        sender.send(addresses).unwrap();
+                                    }
+                                }
+                                Ok(utxo_set_stats)
+                            })
+                        })
+                        .collect();
+                    drop((txo_senders, address_senders));
+                    let mut utxo_set_stats = UtxoSetStats::new();
+                    for decoder in decoders {
+                        utxo_set_stats.merge(&decoder.join().unwrap()?);
+                    }
+                    Ok(utxo_set_stats)
+                })
+            })
+        })?;
+        store.utxo_set_stats = Box::new(utxo_set_stats);
+        store.muhash = Some(Box::new(MuHash3072::from_reader(reader)?));
+        while !reader.is_at_end() {
+            let section = reader.read_u8(&mut None).field("section")?;
+            let length = reader.read_u64_le(&mut None).field("section length")?;
+            match section {
+                SECTION_HISTORY if options.history => store.history = Some(read_history(reader, logger)?),
+                SECTION_SPENDS if options.spends => store.spends = Some(read_spends(reader, logger)?),
+                SECTION_STATS if options.stats => store.stats = Some(read_stats(reader, logger)?),
+                SECTION_OP_RETURNS if options.op_returns => store.op_returns = Some(read_op_returns(reader, logger)?),
+                _ => {
+                    let offset = reader.offset();
+                    let length = length
+                        .try_into()
+                        .map_err(|_| ParseError::new(offset, "section length", ParseErrorKind::InvalidValue(length)))?;
+                    reader.skip(length, &mut None).field("section")?
+                }
+            }
+        }
+        if store.history.is_none() && options.history {
+            logger.log("store file has no history index");
+            return Ok(None);
         }
-        store
+        if store.spends.is_none() && options.spends {
+            logger.log("store file has no spend index");
+            return Ok(None);
+        }
+        if store.stats.is_none() && options.stats {
+            logger.log("store file has no stats index");
+            return Ok(None);
+        }
+        if store.op_returns.is_none() && options.op_returns {
+            logger.log("store file has no OP_RETURN index");
+            return Ok(None);
+        }
+        Ok(Some(store))
     }
 
     pub fn to_writer(&self, writer: &mut BufferWriter, logger: &Logger) {
-        let mut logger = PartialLogger::new(1000000, logger);
-        writer.write_u32(self.unspent_txo_address_map.len().try_into().unwrap());
-        for (txo, (value, address)) in &self.unspent_txo_address_map {
-            logger.log(|index| format!("writing utxo {} to buffer...", index));
-            writer.write_buffer(&txo.hash);
-            writer.write_u32(txo.index);
-            writer.write_u64(*value);
-            match address {
-                Some(address) => {
-                    writer.write_u8(1);
-                    writer.write_buffer(&address.to_vec());
+        let mut partial_logger = PartialLogger::new(1000000, logger);
+        let txos = self.sorted_transaction_outputs();
+        writer.write_u32(txos.len().try_into().unwrap());
+        for txos in group_by_hash(&txos) {
+            writer.write_hash(txos[0].0.hash);
+            writer.write_b128_var_int(txos.len().try_into().unwrap());
+            let mut next_output_index = 0;
+            for (txo, coin) in txos {
+                partial_logger.log(|index| format!("writing utxo {} to buffer...", index));
+                writer.write_b128_var_int((txo.index - next_output_index).into());
+                writer.write_b128_var_int(coin.code().into());
+                writer.write_b128_var_int(compress_amount(coin.value));
+                coin.script.to_writer(writer);
+                next_output_index = txo.index + 1;
+            }
+        }
+        self.muhash.as_ref().unwrap().to_writer(writer);
+        if let Some(history) = &self.history {
+            write_section(writer, SECTION_HISTORY, |writer| write_history(writer, history, logger));
+        }
+        if let Some(spends) = &self.spends {
+            write_section(writer, SECTION_SPENDS, |writer| write_spends(writer, spends, logger));
+        }
+        if let Some(stats) = &self.stats {
+            write_section(writer, SECTION_STATS, |writer| write_stats(writer, stats, logger));
+        }
+        if let Some(op_returns) = &self.op_returns {
+            write_section(writer, SECTION_OP_RETURNS, |writer| write_op_returns(writer, op_returns, logger));
+        }
+    }
+
+    pub fn iterate_unspent_transaction_outputs(&self, mut callback: impl FnMut(&TransactionOutput, &Coin)) {
+        for (txo, coin) in self.sorted_transaction_outputs() {
+            callback(txo, coin);
+        }
+    }
+
+    pub fn iterate_balances(&self, mut callback: impl FnMut(&Address, u64)) {
+        for (address, txos) in &self.address_unspent_txos_map {
+            let balance = txos
+                .iter()
+                .map(|(txo, _)| self.unspent_txo_address_map.get(txo).unwrap().value)
+                .sum();
+            callback(address, balance);
+        }
+    }
+
+    pub fn utxo_set_stats(&self) -> &UtxoSetStats {
+        &self.utxo_set_stats
+    }
+
+    pub fn funded_addresses(&self) -> usize {
+        self.address_unspent_txos_map.len()
+    }
+
+    /// Checks that the UTXO set, the address index and the optional indexes agree, calling `report` with the name of the
+    /// failed check and a description of each discrepancy.
+    pub fn check(&self, mut report: impl FnMut(&str, String)) {
+        let mut utxo_set_stats = UtxoSetStats::new();
+        for (txo, coin) in &self.unspent_txo_address_map {
+            utxo_set_stats.add(coin);
+            if let Some(address) = coin.address() {
+                if self.address_unspent_txos_map.get(&address).and_then(|txos| txos.get(txo)).is_none() {
+                    report(
+                        "address_index",
+                        format!(
+                            "utxo {}:{} missing from address {}",
+                            reverse_hex::encode(txo.hash),
+                            txo.index,
+                            address
+                        ),
+                    );
                 }
-                None => {
-                    writer.write_u8(0);
+            }
+        }
+        if &utxo_set_stats != self.utxo_set_stats() {
+            report(
+                "utxo_set_stats",
+                format!("expected {:?}, found {:?}", utxo_set_stats, self.utxo_set_stats()),
+            );
+        }
+        for (address, txos) in &self.address_unspent_txos_map {
+            if txos.len() == 0 {
+                report("address_index", format!("address {} has no utxo", address));
+            }
+            let mut balance = 0;
+            for (txo, _) in txos {
+                match self.unspent_txo_address_map.get(txo) {
+                    Some(coin) if coin.address().as_ref() == Some(address) => balance += coin.value,
+                    Some(_) => report(
+                        "address_index",
+                        format!(
+                            "utxo {}:{} does not belong to address {}",
+                            reverse_hex::encode(txo.hash),
+                            txo.index,
+                            address
+                        ),
+                    ),
+                    None => report(
+                        "address_index",
+                        format!(
+                            "address {} has unknown utxo {}:{}",
+                            address,
+                            reverse_hex::encode(txo.hash),
+                            txo.index
+                        ),
+                    ),
+                }
+            }
+            if let Some(stats) = self.address_stats(address) {
+                if stats.received.checked_sub(stats.sent) != Some(balance) {
+                    report(
+                        "stats_index",
+                        format!(
+                            "address {} has a balance of {} but received {} and sent {}",
+                            address, balance, stats.received, stats.sent
+                        ),
+                    );
+                }
+            }
+        }
+        if let Some(spends) = &self.spends {
+            for (txo, _) in spends {
+                if self.unspent_txo_address_map.get(txo).is_some() {
+                    report(
+                        "spend_index",
+                        format!("utxo {}:{} is recorded as spent", reverse_hex::encode(txo.hash), txo.index),
+                    );
                 }
             }
         }
     }
-}
 
-impl ReadonlyTransactionStoreBackendTrait for IndexedTransactionStoreBackend {
-    fn iterate_transaction_outputs(&self, address: &Address, mut callback: impl FnMut(&TransactionOutput, u64)) {
-        if let Some(txos) = self.address_unspent_txos_map.get(address) {
-            for (txo, value) in txos {
-                callback(txo, *value);
+    pub fn muhash(&self) -> Option<&MuHash3072> {
+        self.muhash.as_deref()
+    }
+
+    /// Computes Bitcoin Core's `hash_serialized_3`, the double SHA256 of the UTXO set serialized in outpoint order.
+    pub fn hash_serialized(&self) -> [u8; 32] {
+        let mut hasher = Hasher::new();
+        for (txo, coin) in self.sorted_transaction_outputs() {
+            if let Some(data) = hash_data(txo, coin) {
+                hasher.update(&data);
             }
         }
+        hasher.digest()
     }
-}
 
-impl TransactionStoreBackendTrait for IndexedTransactionStoreBackend {
-    fn has_transaction_output(&self, txo: &TransactionOutput) -> bool {
-        self.unspent_txo_address_map.get(txo).is_some()
-    }
-
-    fn spend_transaction_output(&mut self, txo: &TransactionOutput) -> bool {
-        match self.unspent_txo_address_map.remove(txo) {
-            Some((_, address)) => {
-                if let Some(address) = address {
-                    let unspent_txos = self.address_unspent_txos_map.get_mut(&address).unwrap();
-                    unspent_txos.remove(txo);
-                    if unspent_txos.len() == 0 {
-                        self.address_unspent_txos_map.remove(&address);
-                    }
+    /// Entry of the transaction in the history of the address, added when the transaction is not its last entry yet.
+    fn history_entry_mut(&mut self, address: Address, hash: [u8; 32], height: u32) -> Option<&mut HistoryEntry> {
+        let history = self.history.as_mut()?;
+        if history.get(&address).is_none() {
+            history.insert(address.clone(), Arc::new(ChunkedVec::new()));
+        }
+        let entries = Arc::make_mut(history.get_mut(&address).unwrap());
+        if entries.last().map(|entry| entry.hash) != Some(hash) {
+            entries.push(HistoryEntry::new(hash, height));
+        }
+        entries.last_mut()
+    }
+
+    fn address_stats_mut(&mut self, address: Address, height: u32) -> Option<&mut AddressStats> {
+        let stats = self.stats.as_mut()?;
+        if stats.get(&address).is_none() {
+            stats.insert(address.clone(), AddressStats::new(height));
+        }
+        stats.get_mut(&address)
+    }
+
+    /// Records the spending of a coin in the indexes, whether the coin belongs to this store or to the store it augments.
+    fn index_spend(&mut self, txo: &TransactionOutput, coin: &Coin, spender: &Spender) {
+        if let Some(address) = coin.address() {
+            if let Some(stats) = self.address_stats_mut(address.clone(), spender.height) {
+                stats.send(coin.value, spender.height);
+            }
+            if let Some(entry) = self.history_entry_mut(address, spender.hash, spender.height) {
+                entry.sent.push(SentOutput {
+                    txo: txo.clone(),
+                    input_index: spender.index,
+                    value: coin.value,
+                });
+            }
+        }
+        if let Some(spends) = &mut self.spends {
+            spends.insert(txo.clone(), spender.clone());
+        }
+    }
+
+    /// Undoes `index_spend` except for the history entry of the spending transaction.
+    fn unindex_spend(&mut self, txo: &TransactionOutput, coin: &Coin) -> Result<(), Error> {
+        if let Some(address) = coin.address() {
+            self.update_address_stats(&address, |stats| stats.undo_send(coin.value))?;
+        }
+        if let Some(spends) = &mut self.spends {
+            spends.remove(txo);
+        }
+        Ok(())
+    }
+
+    /// Updates the stats of the address, dropping them once they count no output.
+    fn update_address_stats(
+        &mut self,
+        address: &Address,
+        update: impl FnOnce(&mut AddressStats) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if let Some(stats) = self.stats.as_mut() {
+            if let Some(address_stats) = stats.get_mut(address) {
+                update(address_stats).map_err(|error| Error::new(format!("{}: {}", address, error)))?;
+                if address_stats.is_empty() {
+                    stats.remove(address);
                 }
-                true
             }
-            None => false,
+        }
+        Ok(())
+    }
+
+    /// Removes the entries of the transactions from the history of the address.
+    fn remove_history_entries(&mut self, address: &Address, hashes: &HashSet<[u8; 32]>) {
+        if let Some(history) = self.history.as_mut() {
+            if let Some(entries) = history.get(address) {
+                let entries: ChunkedVec<_> = entries.iter().filter(|entry| !hashes.contains(&entry.hash)).cloned().collect();
+                match entries.is_empty() {
+                    true => history.remove(address),
+                    false => history.insert(address.clone(), Arc::new(entries)),
+                };
+            }
         }
     }
 
-    fn add_transaction_output(&mut self, txo: TransactionOutput, address: Option<Address>, value: u64) {
-        self.unspent_txo_address_map.insert(txo.clone(), (value, address.clone()));
+    /// Adds the coin to the UTXO set and to the address index.
+    fn add_unspent_transaction_output(&mut self, txo: TransactionOutput, coin: Coin) {
+        if let Some(muhash) = &mut self.muhash {
+            // Duplicate coinbase transactions (BIP30) overwrite the outputs they share with the original transaction.
+            if let Some(data) = self.unspent_txo_address_map.get(&txo).and_then(|coin| hash_data(&txo, coin)) {
+                muhash.remove(&data);
+            }
+            if let Some(data) = hash_data(&txo, &coin) {
+                muhash.insert(&data);
+            }
+        }
+        let address = coin.address();
+        self.utxo_set_stats.add(&coin);
+        if let Some(coin) = self.unspent_txo_address_map.insert(txo.clone(), coin) {
+            self.utxo_set_stats.remove(&coin);
+        }
         if let Some(address) = address {
             match self.address_unspent_txos_map.get_mut(&address) {
                 Some(unspent_txos) => {
-                    unspent_txos.insert(txo.clone(), value);
+                    unspent_txos.insert(txo, ());
                 }
                 None => {
                     let mut unspent_txos = TransactionOutputHashMap::new();
-                    unspent_txos.insert(txo.clone(), value);
-                    self.address_unspent_txos_map.insert(address.clone(), unspent_txos);
+                    unspent_txos.insert(txo, ());
+                    self.address_unspent_txos_map.insert(address, unspent_txos);
                 }
             };
         }
     }
+
+    /// Removes the coin from the UTXO set and from the address index.
+    fn remove_unspent_transaction_output(&mut self, txo: &TransactionOutput) -> Option<Coin> {
+        let coin = self.unspent_txo_address_map.remove(txo)?;
+        if let (Some(muhash), Some(data)) = (&mut self.muhash, hash_data(txo, &coin)) {
+            muhash.remove(&data);
+        }
+        self.utxo_set_stats.remove(&coin);
+        if let Some(address) = coin.address() {
+            let unspent_txos = self.address_unspent_txos_map.get_mut(&address).unwrap();
+            unspent_txos.remove(txo);
+            if unspent_txos.len() == 0 {
+                self.address_unspent_txos_map.remove(&address);
+            }
+        }
+        Some(coin)
+    }
+
+    fn sorted_transaction_outputs(&self) -> Vec<(&TransactionOutput, &Coin)> {
+        let mut txos: Vec<_> = self.unspent_txo_address_map.iter().collect();
+        txos.sort_unstable_by_key(|(txo, _)| *txo);
+        txos
+    }
+}
+
+fn hash_data(txo: &TransactionOutput, coin: &Coin) -> Option<Vec<u8>> {
+    if coin.is_in_utxo_set() {
+        let mut writer = BufferWriter::new();
+        coin.write_hash_data(txo, &mut writer);
+        Some(writer.buffer())
+    } else {
+        None
+    }
+}
+
+/// Number of utxos decoded at once by each thread when loading a store file.
+const CHUNK_SIZE: u32 = 1000000;
+
+/// Splits the `count` serialized utxos into chunks of whole transactions, without decoding them.
+fn split_transaction_outputs<'a>(reader: &mut HashingBufferReader<'a>, count: u32) -> ParseResult<Vec<&'a [u8]>> {
+    let mut chunks = Vec::new();
+    let mut index: u32 = 0;
+    while index < count {
+        let mut scanner = reader.clone();
+        let end = count.min(index.saturating_add(CHUNK_SIZE));
+        while index < end {
+            scanner.skip(32, &mut None).field("hash")?;
+            let offset = scanner.offset();
+            let outputs: u32 = scanner.read_b128_var_int_as(&mut None).field("output count")?;
+            for _ in 0..outputs {
+                scanner.read_b128_var_int(&mut None).field("output index")?;
+                scanner.read_b128_var_int(&mut None).field("code")?;
+                scanner.read_b128_var_int(&mut None).field("value")?;
+                CompactScript::skip(&mut scanner)?;
+            }
+            index = index
+                .checked_add(outputs)
+                .ok_or_else(|| ParseError::new(offset, "output count", ParseErrorKind::InvalidValue(outputs.into())))?;
+        }
+        chunks.push(reader.read_buffer(scanner.offset() - reader.offset(), &mut None)?);
+    }
+    Ok(chunks)
+}
+
+fn read_transaction_outputs(chunk: &[u8]) -> ParseResult<Vec<(TransactionOutput, Coin)>> {
+    let mut reader = HashingBufferReader::new(chunk);
+    let mut coins = Vec::new();
+    while !reader.is_at_end() {
+        let hash = reader.read_hash(&mut None)?;
+        let outputs = reader.read_b128_var_int(&mut None).field("output count")?;
+        let mut next_output_index: u32 = 0;
+        for _ in 0..outputs {
+            let offset = reader.offset();
+            let delta: u32 = reader.read_b128_var_int_as(&mut None).field("output index")?;
+            let output_index = next_output_index
+                .checked_add(delta)
+                .ok_or_else(|| ParseError::new(offset, "output index", ParseErrorKind::InvalidValue(delta.into())))?;
+            let code: u32 = reader.read_b128_var_int_as(&mut None).field("code")?;
+            let value = decompress_amount(reader.read_b128_var_int(&mut None).field("value")?);
+            let script = CompactScript::from_reader(&mut reader)?;
+            let coin = Coin {
+                value,
+                height: code >> 1,
+                coinbase: code & 1 == 1,
+                script,
+            };
+            coins.push((TransactionOutput::new(hash, output_index), coin));
+            next_output_index = output_index.wrapping_add(1);
+        }
+    }
+    Ok(coins)
+}
+
+fn write_section(writer: &mut BufferWriter, section: u8, write: impl FnOnce(&mut BufferWriter)) {
+    writer.write_u8(section);
+    let offset = writer.offset();
+    writer.write_u64(0);
+    write(writer);
+    let length = writer.offset() - offset - 8;
+    writer.write_u64_at(offset, length.try_into().unwrap());
+}
+
+fn read_address(reader: &mut HashingBufferReader) -> ParseResult<Address> {
+    let offset = reader.offset();
+    let buffer = reader.read_buffer(21, &mut None).field("address")?;
+    Address::from_slice(buffer).map_err(|_| ParseError::new(offset, "address", ParseErrorKind::InvalidValue(buffer[0].into())))
+}
+
+fn read_history(reader: &mut HashingBufferReader, logger: &Logger) -> ParseResult<History> {
+    let mut logger = PartialLogger::new(1000000, logger);
+    let count: usize = reader.read_b128_var_int_as(&mut None).field("address count")?;
+    let mut history = History::with_capacity(count.min(reader.remaining()));
+    for _ in 0..count {
+        let address = read_address(reader)?;
+        let length = reader.read_b128_var_int(&mut None).field("history length")?;
+        let mut entries = ChunkedVec::new();
+        for _ in 0..length {
+            logger.log(|index| format!("reading history entry {} from buffer...", index));
+            entries.push(HistoryEntry::from_reader(reader)?);
+        }
+        history.insert(address, Arc::new(entries));
+    }
+    Ok(history)
+}
+
+fn write_history(writer: &mut BufferWriter, history: &History, logger: &Logger) {
+    let mut logger = PartialLogger::new(1000000, logger);
+    writer.write_b128_var_int(history.len().try_into().unwrap());
+    for (address, entries) in history {
+        writer.write_buffer(&address.to_vec());
+        writer.write_b128_var_int(entries.len().try_into().unwrap());
+        for entry in entries.iter() {
+            logger.log(|index| format!("writing history entry {} to buffer...", index));
+            entry.to_writer(writer);
+        }
+    }
+}
+
+fn read_spends(reader: &mut HashingBufferReader, logger: &Logger) -> ParseResult<ShardedTransactionOutputHashMap<Spender>> {
+    let mut logger = PartialLogger::new(1000000, logger);
+    let count: usize = reader.read_b128_var_int_as(&mut None).field("spend count")?;
+    let mut spends = ShardedTransactionOutputHashMap::with_capacity(count.min(reader.remaining()));
+    for _ in 0..count {
+        logger.log(|index| format!("reading spend {} from buffer...", index));
+        let hash = reader.read_hash(&mut None)?;
+        let index = reader.read_b128_var_int_as(&mut None).field("output index")?;
+        let spender = Spender {
+            hash: reader.read_hash(&mut None).field("spender hash")?,
+            index: reader.read_b128_var_int_as(&mut None).field("spender index")?,
+            height: reader.read_b128_var_int_as(&mut None).field("spender height")?,
+        };
+        spends.insert(TransactionOutput::new(hash, index), spender);
+    }
+    Ok(spends)
+}
+
+fn write_spends(writer: &mut BufferWriter, spends: &ShardedTransactionOutputHashMap<Spender>, logger: &Logger) {
+    let mut logger = PartialLogger::new(1000000, logger);
+    writer.write_b128_var_int(spends.len().try_into().unwrap());
+    for (txo, spender) in spends {
+        logger.log(|index| format!("writing spend {} to buffer...", index));
+        writer.write_hash(txo.hash);
+        writer.write_b128_var_int(txo.index.into());
+        writer.write_hash(spender.hash);
+        writer.write_b128_var_int(spender.index.into());
+        writer.write_b128_var_int(spender.height.into());
+    }
+}
+
+fn read_stats(reader: &mut HashingBufferReader, logger: &Logger) -> ParseResult<ShardedAddressHashMap<AddressStats>> {
+    let mut logger = PartialLogger::new(1000000, logger);
+    let count: usize = reader.read_b128_var_int_as(&mut None).field("address count")?;
+    let mut stats = ShardedAddressHashMap::with_capacity(count.min(reader.remaining()));
+    for _ in 0..count {
+        logger.log(|index| format!("reading address stats {} from buffer...", index));
+        let address = read_address(reader)?;
+        stats.insert(address, AddressStats::from_reader(reader)?);
+    }
+    Ok(stats)
+}
+
+fn write_stats(writer: &mut BufferWriter, stats: &ShardedAddressHashMap<AddressStats>, logger: &Logger) {
+    let mut logger = PartialLogger::new(1000000, logger);
+    writer.write_b128_var_int(stats.len().try_into().unwrap());
+    for (address, stats) in stats {
+        logger.log(|index| format!("writing address stats {} to buffer...", index));
+        writer.write_buffer(&address.to_vec());
+        stats.to_writer(writer);
+    }
+}
+
+fn read_op_returns(reader: &mut HashingBufferReader, logger: &Logger) -> ParseResult<OpReturnIndex> {
+    let mut logger = PartialLogger::new(1000000, logger);
+    let count: usize = reader.read_b128_var_int_as(&mut None).field("op_return count")?;
+    let mut op_returns = OpReturnIndex::with_capacity(count.min(reader.remaining()));
+    for _ in 0..count {
+        logger.log(|index| format!("reading op_return {} from buffer...", index));
+        op_returns.add(OpReturn::from_reader(reader)?);
+    }
+    Ok(op_returns)
+}
+
+fn write_op_returns(writer: &mut BufferWriter, op_returns: &OpReturnIndex, logger: &Logger) {
+    let mut logger = PartialLogger::new(1000000, logger);
+    writer.write_b128_var_int(op_returns.entries().len().try_into().unwrap());
+    for entry in op_returns.entries().iter() {
+        logger.log(|index| format!("writing op_return {} to buffer...", index));
+        entry.to_writer(writer);
+    }
+}
+
+fn group_by_hash<'a, T>(txos: &'a [(&TransactionOutput, T)]) -> impl Iterator<Item = &'a [(&'a TransactionOutput, T)]> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let hash = txos.get(offset)?.0.hash;
+        let length = txos[offset..].iter().take_while(|(txo, _)| txo.hash == hash).count();
+        offset += length;
+        Some(&txos[offset - length..offset])
+    })
+}
+
+impl ReadonlyTransactionStoreBackendTrait for IndexedTransactionStoreBackend {
+    fn unspent_transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin> {
+        self.unspent_txo_address_map.get(txo)
+    }
+
+    fn iterate_transaction_outputs(&self, address: &Address, mut callback: impl FnMut(&TransactionOutput, &Coin)) {
+        if let Some(txos) = self.address_unspent_txos_map.get(address) {
+            for (txo, _) in txos {
+                callback(txo, self.unspent_txo_address_map.get(txo).unwrap());
+            }
+        }
+    }
+
+    fn address_stats(&self, address: &Address) -> Option<AddressStats> {
+        self.stats.as_ref().and_then(|stats| stats.get(address)).cloned()
+    }
+
+    fn history_len(&self, address: &Address) -> usize {
+        match self.history.as_ref().and_then(|history| history.get(address)) {
+            Some(entries) => entries.len(),
+            None => 0,
+        }
+    }
+
+    fn spender(&self, txo: &TransactionOutput) -> Option<&Spender> {
+        self.spends.as_ref().and_then(|spends| spends.get(txo))
+    }
+
+    fn iterate_history(&self, address: &Address, offset: usize, limit: usize, mut callback: impl FnMut(&HistoryEntry)) {
+        if let Some(entries) = self.history.as_ref().and_then(|history| history.get(address)) {
+            for entry in entries.iter_rev(offset).take(limit) {
+                callback(entry);
+            }
+        }
+    }
+
+    fn iterate_op_returns(&self, prefix: &[u8], offset: usize, limit: usize, callback: impl FnMut(&OpReturn)) -> usize {
+        match &self.op_returns {
+            Some(op_returns) => op_returns.iterate_prefix(prefix, offset, limit, callback),
+            None => 0,
+        }
+    }
+
+    fn iterate_op_returns_by_hash(&self, hash: &[u8; 32], callback: impl FnMut(&OpReturn)) {
+        if let Some(op_returns) = &self.op_returns {
+            op_returns.iterate_hash(hash, callback);
+        }
+    }
+}
+
+impl TransactionStoreBackendTrait for IndexedTransactionStoreBackend {
+    fn index_options(&self) -> IndexOptions {
+        IndexOptions {
+            history: self.history.is_some(),
+            spends: self.spends.is_some(),
+            stats: self.stats.is_some(),
+            op_returns: self.op_returns.is_some(),
+        }
+    }
+
+    fn transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin> {
+        self.unspent_txo_address_map.get(txo)
+    }
+
+    fn spend_transaction_output(&mut self, txo: &TransactionOutput, spender: &Spender) -> bool {
+        match self.remove_unspent_transaction_output(txo) {
+            Some(coin) => {
+                self.index_spend(txo, &coin, spender);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_transaction_output(&mut self, txo: TransactionOutput, coin: Coin) {
+        if let Some(address) = coin.address() {
+            let (value, height) = (coin.value, coin.height);
+            if let Some(stats) = self.address_stats_mut(address.clone(), height) {
+                stats.receive(value, height);
+            }
+            if let Some(entry) = self.history_entry_mut(address, txo.hash, height) {
+                entry.received.push((txo.index, value));
+            }
+        }
+        self.add_unspent_transaction_output(txo, coin);
+    }
+
+    fn add_op_return(&mut self, txo: TransactionOutput, height: u32, script: &[u8]) {
+        if let Some(op_returns) = &mut self.op_returns {
+            op_returns.add(OpReturn::new(txo, height, script));
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct IntermediaryTransactionStoreBackend {
-    spent_txos: Vec<TransactionOutput>,
-    unspent_txos: TransactionOutputHashMap<(Option<Address>, u64)>,
+    spent_txos: Vec<(TransactionOutput, Spender)>,
+    unspent_txos: TransactionOutputHashMap<Coin>,
+}
+
+impl Default for IntermediaryTransactionStoreBackend {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IntermediaryTransactionStoreBackend {
@@ -124,59 +801,137 @@ impl IntermediaryTransactionStoreBackend {
         }
     }
 
-    pub fn spent_txos(&self) -> &Vec<TransactionOutput> {
+    pub fn spent_txos(&self) -> &Vec<(TransactionOutput, Spender)> {
         &self.spent_txos
     }
 
-    pub fn unspent_txos(&self) -> &TransactionOutputHashMap<(Option<Address>, u64)> {
+    pub fn unspent_txos(&self) -> &TransactionOutputHashMap<Coin> {
         &self.unspent_txos
     }
 }
 
 impl TransactionStoreBackendTrait for IntermediaryTransactionStoreBackend {
-    fn has_transaction_output(&self, txo: &TransactionOutput) -> bool {
-        self.unspent_txos.get(txo).is_some()
+    fn index_options(&self) -> IndexOptions {
+        IndexOptions::none()
+    }
+
+    fn transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin> {
+        self.unspent_txos.get(txo)
     }
 
-    fn spend_transaction_output(&mut self, txo: &TransactionOutput) -> bool {
+    fn spend_transaction_output(&mut self, txo: &TransactionOutput, spender: &Spender) -> bool {
         match self.unspent_txos.remove(txo) {
             Some(_) => true,
             None => {
-                self.spent_txos.push(txo.clone());
+                self.spent_txos.push((txo.clone(), spender.clone()));
                 false
             }
         }
     }
 
-    fn add_transaction_output(&mut self, txo: TransactionOutput, address: Option<Address>, value: u64) {
-        self.unspent_txos.insert(txo, (address, value));
+    fn add_transaction_output(&mut self, txo: TransactionOutput, coin: Coin) {
+        self.unspent_txos.insert(txo, coin);
     }
+
+    fn add_op_return(&mut self, _txo: TransactionOutput, _height: u32, _script: &[u8]) {}
 }
 
+#[derive(Debug, Clone)]
 pub struct TransactionStoreAugmentation {
-    spent_txos: HashSet<TransactionOutput>,
+    spent_txos: ShardedTransactionOutputHashMap<()>,
     store: IndexedTransactionStoreBackend,
 }
 
+impl Default for TransactionStoreAugmentation {
+    fn default() -> Self {
+        Self::new(&IndexOptions::none())
+    }
+}
+
 impl TransactionStoreAugmentation {
-    fn new() -> Self {
+    fn new(options: &IndexOptions) -> Self {
+        Self::with_shards(options, 1)
+    }
+
+    /// Empty augmentation whose maps have `shards` shards, so that a clone of it that gets modified only copies the
+    /// shards it modifies.
+    pub fn with_shards(options: &IndexOptions, shards: usize) -> Self {
         Self {
-            spent_txos: HashSet::new(),
-            store: IndexedTransactionStoreBackend::new(),
+            spent_txos: ShardedTransactionOutputHashMap::with_shards(shards),
+            store: IndexedTransactionStoreBackend::with_shards(options, shards),
         }
     }
+
+    /// Removes transactions that were added to the augmentation, given with the coins they spent, each one before the
+    /// transactions whose outputs it spends. The outputs of a removed transaction must not be spent by a transaction that
+    /// is kept. Fails when the address stats do not count an output of a removed transaction, leaving the augmentation
+    /// partially updated.
+    pub fn remove_transactions<'t>(&mut self, transactions: impl IntoIterator<Item = (&'t Transaction, &'t [Coin])>) -> Result<(), Error> {
+        let mut history: HashMap<Address, HashSet<[u8; 32]>> = HashMap::new();
+        let mut op_returns = HashSet::new();
+        for (transaction, spent_coins) in transactions {
+            for (index, output) in transaction.outputs.iter().enumerate() {
+                if script::is_op_return(&output.script) {
+                    op_returns.insert(transaction.hash);
+                    continue;
+                }
+                let txo = TransactionOutput::new(transaction.hash, index.try_into().unwrap());
+                if let Some(coin) = self.store.remove_unspent_transaction_output(&txo) {
+                    if let Some(address) = coin.address() {
+                        self.store.update_address_stats(&address, |stats| stats.undo_receive(coin.value))?;
+                        history.entry(address).or_default().insert(transaction.hash);
+                    }
+                }
+            }
+            for (input, coin) in transaction.inputs.iter().zip(spent_coins) {
+                // Coins of the store it augments are recorded as spent, the ones of the augmentation were removed from it.
+                if self.spent_txos.remove(&input.txo).is_none() {
+                    self.store.add_unspent_transaction_output(input.txo.clone(), coin.clone());
+                }
+                self.store.unindex_spend(&input.txo, coin)?;
+                if let Some(address) = coin.address() {
+                    history.entry(address).or_default().insert(transaction.hash);
+                }
+            }
+        }
+        for (address, hashes) in &history {
+            self.store.remove_history_entries(address, hashes);
+        }
+        if let (Some(index), false) = (&mut self.store.op_returns, op_returns.is_empty()) {
+            index.retain(|entry| !op_returns.contains(&entry.txo.hash));
+        }
+        Ok(())
+    }
 }
 
 pub struct AugmentedTransactionStoreBackend<'a, T: TransactionStoreBackendTrait> {
     base_store: &'a T,
+    /// Augmentations stacked on the base store, from the oldest to the newest, which this one builds on.
+    layers: Vec<&'a TransactionStoreAugmentation>,
     store: TransactionStoreAugmentation,
 }
 
 impl<'a, T: TransactionStoreBackendTrait> AugmentedTransactionStoreBackend<'a, T> {
     pub fn new(base_store: &'a T) -> Self {
+        Self::with_layers(base_store, Vec::new())
+    }
+
+    /// Backend augmenting `base_store` seen through `layers`.
+    pub fn with_layers(base_store: &'a T, layers: Vec<&'a TransactionStoreAugmentation>) -> Self {
+        let augmentation = TransactionStoreAugmentation::new(&base_store.index_options());
+        Self::with_augmentation(base_store, layers, augmentation)
+    }
+
+    /// Backend that goes on from an augmentation built on `base_store` seen through `layers`.
+    pub fn with_augmentation(
+        base_store: &'a T,
+        layers: Vec<&'a TransactionStoreAugmentation>,
+        augmentation: TransactionStoreAugmentation,
+    ) -> Self {
         Self {
             base_store,
-            store: TransactionStoreAugmentation::new(),
+            layers,
+            store: augmentation,
         }
     }
 
@@ -187,50 +942,164 @@ impl<'a, T: TransactionStoreBackendTrait> AugmentedTransactionStoreBackend<'a, T
     pub fn take_augmentation(self) -> TransactionStoreAugmentation {
         self.store
     }
+
+    /// Unspent output of the base store seen through the layers.
+    fn base_transaction_output(&self, txo: &TransactionOutput) -> Option<&'a Coin> {
+        for layer in self.layers.iter().rev() {
+            if let Some(coin) = layer.store.transaction_output(txo) {
+                return Some(coin);
+            }
+            if layer.spent_txos.contains_key(txo) {
+                return None;
+            }
+        }
+        self.base_store.transaction_output(txo)
+    }
 }
 
 impl<'a, T: TransactionStoreBackendTrait> TransactionStoreBackendTrait for AugmentedTransactionStoreBackend<'a, T> {
-    fn has_transaction_output(&self, txo: &TransactionOutput) -> bool {
-        self.store.store.has_transaction_output(txo)
-            || (self.store.spent_txos.get(txo).is_none() && self.base_store.has_transaction_output(txo))
+    fn index_options(&self) -> IndexOptions {
+        self.store.store.index_options()
     }
 
-    fn spend_transaction_output(&mut self, txo: &TransactionOutput) -> bool {
-        if self.store.store.spend_transaction_output(txo) {
-            true
-        } else {
-            self.base_store.has_transaction_output(txo) && self.store.spent_txos.insert(txo.clone())
+    fn transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin> {
+        match self.store.store.transaction_output(txo) {
+            Some(coin) => Some(coin),
+            None if self.store.spent_txos.contains_key(txo) => None,
+            None => self.base_transaction_output(txo),
+        }
+    }
+
+    fn spend_transaction_output(&mut self, txo: &TransactionOutput, spender: &Spender) -> bool {
+        if self.store.store.spend_transaction_output(txo, spender) {
+            return true;
+        }
+        match self.base_transaction_output(txo) {
+            Some(coin) if self.store.spent_txos.insert(txo.clone(), ()).is_none() => {
+                self.store.store.index_spend(txo, coin, spender);
+                true
+            }
+            _ => false,
         }
     }
 
-    fn add_transaction_output(&mut self, txo: TransactionOutput, address: Option<Address>, value: u64) {
-        self.store.store.add_transaction_output(txo, address, value);
+    fn add_transaction_output(&mut self, txo: TransactionOutput, coin: Coin) {
+        self.store.store.add_transaction_output(txo, coin);
+    }
+
+    fn add_op_return(&mut self, txo: TransactionOutput, height: u32, script: &[u8]) {
+        self.store.store.add_op_return(txo, height, script);
     }
 }
 
+/// Store seen through augmentations stacked on top of it, from the oldest to the newest.
 pub struct ReadonlyAugmentedTransactionStoreBackend<'a, T: ReadonlyTransactionStoreBackendTrait> {
     store: &'a T,
-    augmentation: Option<&'a TransactionStoreAugmentation>,
+    augmentations: Vec<&'a TransactionStoreAugmentation>,
 }
 
 impl<'a, T: ReadonlyTransactionStoreBackendTrait> ReadonlyAugmentedTransactionStoreBackend<'a, T> {
-    pub fn new(store: &'a T, augmentation: Option<&'a TransactionStoreAugmentation>) -> Self {
-        Self { store, augmentation }
+    pub fn new(store: &'a T, augmentations: Vec<&'a TransactionStoreAugmentation>) -> Self {
+        Self { store, augmentations }
+    }
+
+    fn is_spent_from(&self, index: usize, txo: &TransactionOutput) -> bool {
+        self.augmentations[index..]
+            .iter()
+            .any(|augmentation| augmentation.spent_txos.contains_key(txo))
     }
 }
 
 impl<'a, T: ReadonlyTransactionStoreBackendTrait> ReadonlyTransactionStoreBackendTrait for ReadonlyAugmentedTransactionStoreBackend<'a, T> {
-    fn iterate_transaction_outputs(&self, address: &Address, mut callback: impl FnMut(&TransactionOutput, u64)) {
-        match self.augmentation {
-            Some(augmentation) => {
-                self.store.iterate_transaction_outputs(address, |txo, value| {
-                    if !augmentation.spent_txos.contains(txo) {
-                        callback(txo, value);
-                    }
-                });
-                augmentation.store.iterate_transaction_outputs(address, callback);
+    fn unspent_transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin> {
+        for augmentation in self.augmentations.iter().rev() {
+            if let Some(coin) = augmentation.store.unspent_transaction_output(txo) {
+                return Some(coin);
             }
-            None => self.store.iterate_transaction_outputs(address, callback),
+            if augmentation.spent_txos.contains_key(txo) {
+                return None;
+            }
+        }
+        self.store.unspent_transaction_output(txo)
+    }
+
+    fn iterate_transaction_outputs(&self, address: &Address, mut callback: impl FnMut(&TransactionOutput, &Coin)) {
+        self.store.iterate_transaction_outputs(address, |txo, coin| {
+            if !self.is_spent_from(0, txo) {
+                callback(txo, coin);
+            }
+        });
+        for (index, augmentation) in self.augmentations.iter().enumerate() {
+            augmentation.store.iterate_transaction_outputs(address, |txo, coin| {
+                if !self.is_spent_from(index + 1, txo) {
+                    callback(txo, coin);
+                }
+            });
+        }
+    }
+
+    fn address_stats(&self, address: &Address) -> Option<AddressStats> {
+        self.augmentations
+            .iter()
+            .fold(self.store.address_stats(address), |stats, augmentation| {
+                match (stats, augmentation.store.address_stats(address)) {
+                    (Some(stats), Some(augmentation_stats)) => Some(stats.merge(&augmentation_stats)),
+                    (stats, augmentation_stats) => stats.or(augmentation_stats),
+                }
+            })
+    }
+
+    fn history_len(&self, address: &Address) -> usize {
+        let length = self.store.history_len(address);
+        length
+            + self
+                .augmentations
+                .iter()
+                .map(|augmentation| augmentation.store.history_len(address))
+                .sum::<usize>()
+    }
+
+    fn spender(&self, txo: &TransactionOutput) -> Option<&Spender> {
+        match self
+            .augmentations
+            .iter()
+            .rev()
+            .find_map(|augmentation| augmentation.store.spender(txo))
+        {
+            Some(spender) => Some(spender),
+            None => self.store.spender(txo),
+        }
+    }
+
+    fn iterate_history(&self, address: &Address, mut offset: usize, mut limit: usize, mut callback: impl FnMut(&HistoryEntry)) {
+        for augmentation in self.augmentations.iter().rev() {
+            let length = augmentation.store.history_len(address);
+            augmentation.store.iterate_history(address, offset, limit, &mut callback);
+            let skipped = offset.min(length);
+            let taken = (length - skipped).min(limit);
+            offset -= skipped;
+            limit -= taken;
+        }
+        self.store.iterate_history(address, offset, limit, callback);
+    }
+
+    fn iterate_op_returns(&self, prefix: &[u8], mut offset: usize, mut limit: usize, mut callback: impl FnMut(&OpReturn)) -> usize {
+        let mut count = 0;
+        for augmentation in self.augmentations.iter().rev() {
+            let length = augmentation.store.iterate_op_returns(prefix, offset, limit, &mut callback);
+            let skipped = offset.min(length);
+            let taken = (length - skipped).min(limit);
+            offset -= skipped;
+            limit -= taken;
+            count += length;
+        }
+        count + self.store.iterate_op_returns(prefix, offset, limit, callback)
+    }
+
+    fn iterate_op_returns_by_hash(&self, hash: &[u8; 32], mut callback: impl FnMut(&OpReturn)) {
+        for augmentation in self.augmentations.iter().rev() {
+            augmentation.store.iterate_op_returns_by_hash(hash, &mut callback);
         }
+        self.store.iterate_op_returns_by_hash(hash, callback);
     }
 }
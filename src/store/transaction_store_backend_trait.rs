@@ -1,19 +1,80 @@
-use crate::{Address, TransactionOutput};
+use super::{AddressStats, Coin, HistoryEntry, IndexOptions, OpReturn, Spender};
+use crate::{Address, HashSet, TransactionOutput};
 
 pub trait ReadonlyTransactionStoreBackendTrait {
-    fn iterate_transaction_outputs(&self, address: &Address, callback: impl FnMut(&TransactionOutput, u64));
+    fn unspent_transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin>;
+
+    fn iterate_transaction_outputs(&self, address: &Address, callback: impl FnMut(&TransactionOutput, &Coin));
 
     fn balance(&self, address: &Address) -> u64 {
         let mut sum = 0;
-        self.iterate_transaction_outputs(address, |_, value| {
-            sum += value;
+        self.iterate_transaction_outputs(address, |_, coin| {
+            sum += coin.value;
         });
         sum
     }
+
+    fn mature_balance(&self, address: &Address, tip_height: u32) -> u64 {
+        let mut sum = 0;
+        self.iterate_transaction_outputs(address, |_, coin| {
+            if coin.is_mature(tip_height) {
+                sum += coin.value;
+            }
+        });
+        sum
+    }
+
+    /// Stats of the address, when the stats index is enabled and the address has been funded.
+    fn address_stats(&self, address: &Address) -> Option<AddressStats>;
+
+    fn history_len(&self, address: &Address) -> usize;
+
+    /// Input that spent the output, when the spend index is enabled.
+    fn spender(&self, txo: &TransactionOutput) -> Option<&Spender>;
+
+    /// Iterates over at most `limit` history entries of the address, newest first, after skipping `offset` entries.
+    fn iterate_history(&self, address: &Address, offset: usize, limit: usize, callback: impl FnMut(&HistoryEntry));
+
+    /// Iterates over at most `limit` OP_RETURN outputs whose payload starts with `prefix`, newest first, after skipping
+    /// `offset` outputs, and returns the number of OP_RETURN outputs whose payload starts with `prefix`.
+    fn iterate_op_returns(&self, prefix: &[u8], offset: usize, limit: usize, callback: impl FnMut(&OpReturn)) -> usize;
+
+    /// Iterates over the OP_RETURN outputs whose payload has the SHA256 `hash`, newest first.
+    fn iterate_op_returns_by_hash(&self, hash: &[u8; 32], callback: impl FnMut(&OpReturn));
+
+    /// Outputs held by the address at the end of the block at `height` with their value and height, oldest first, rebuilt
+    /// from the history.
+    fn historical_transaction_outputs(&self, address: &Address, height: u32) -> Vec<(TransactionOutput, u64, u32)> {
+        let mut spent_txos = HashSet::new();
+        let mut txos = Vec::new();
+        self.iterate_history(address, 0, usize::MAX, |entry| {
+            if entry.height > height {
+                return;
+            }
+            for output in &entry.sent {
+                spent_txos.insert(output.txo.clone());
+            }
+            for (index, value) in entry.received.iter().rev() {
+                let txo = TransactionOutput::new(entry.hash, *index);
+                if !spent_txos.contains(&txo) {
+                    txos.push((txo, *value, entry.height));
+                }
+            }
+        });
+        txos.reverse();
+        txos
+    }
 }
 
 pub trait TransactionStoreBackendTrait {
-    fn has_transaction_output(&self, txo: &TransactionOutput) -> bool;
-    fn spend_transaction_output(&mut self, txo: &TransactionOutput) -> bool;
-    fn add_transaction_output(&mut self, txo: TransactionOutput, address: Option<Address>, value: u64);
+    fn index_options(&self) -> IndexOptions;
+    fn transaction_output(&self, txo: &TransactionOutput) -> Option<&Coin>;
+    fn spend_transaction_output(&mut self, txo: &TransactionOutput, spender: &Spender) -> bool;
+    fn add_transaction_output(&mut self, txo: TransactionOutput, coin: Coin);
+    /// Records an output starting with OP_RETURN, which is not part of the UTXO set, in the OP_RETURN index.
+    fn add_op_return(&mut self, txo: TransactionOutput, height: u32, script: &[u8]);
+
+    fn has_transaction_output(&self, txo: &TransactionOutput) -> bool {
+        self.transaction_output(txo).is_some()
+    }
 }
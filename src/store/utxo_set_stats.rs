@@ -0,0 +1,81 @@
+use super::{Coin, SCRIPT_TYPES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputsStats {
+    pub count: u64,
+    pub amount: u64,
+}
+
+/// Totals of the UTXO set, overall and by script type, kept up to date as outputs are added and spent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoSetStats {
+    pub total: OutputsStats,
+    pub script_types: [OutputsStats; SCRIPT_TYPES.len()],
+}
+
+impl Default for UtxoSetStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UtxoSetStats {
+    pub fn new() -> Self {
+        let empty = OutputsStats { count: 0, amount: 0 };
+        Self {
+            total: empty,
+            script_types: [empty; SCRIPT_TYPES.len()],
+        }
+    }
+
+    pub fn add(&mut self, coin: &Coin) {
+        for stats in [&mut self.total, &mut self.script_types[coin.script.script_type()]] {
+            stats.count += 1;
+            stats.amount += coin.value;
+        }
+    }
+
+    pub fn remove(&mut self, coin: &Coin) {
+        for stats in [&mut self.total, &mut self.script_types[coin.script.script_type()]] {
+            stats.count -= 1;
+            stats.amount -= coin.value;
+        }
+    }
+
+    /// Adds the totals of `other`, counted over a disjoint set of outputs.
+    pub fn merge(&mut self, other: &UtxoSetStats) {
+        let others = std::iter::once(&other.total).chain(&other.script_types);
+        for (stats, other) in std::iter::once(&mut self.total).chain(&mut self.script_types).zip(others) {
+            stats.count += other.count;
+            stats.amount += other.amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UtxoSetStats;
+    use crate::store::{Coin, CompactScript};
+
+    fn coin(value: u64, script: CompactScript) -> Coin {
+        Coin {
+            value,
+            height: 1,
+            coinbase: false,
+            script,
+        }
+    }
+
+    #[test]
+    fn add() {
+        let mut stats = UtxoSetStats::new();
+        stats.add(&coin(100, CompactScript::P2PKH([0; 20])));
+        stats.add(&coin(200, CompactScript::P2TR([0; 32])));
+        stats.add(&coin(300, CompactScript::Other(Box::new([0x6a]))));
+        stats.remove(&coin(100, CompactScript::P2PKH([0; 20])));
+        assert_eq!((stats.total.count, stats.total.amount), (2, 500));
+        assert_eq!((stats.script_types[0].count, stats.script_types[0].amount), (0, 0));
+        assert_eq!((stats.script_types[4].count, stats.script_types[4].amount), (1, 200));
+        assert_eq!((stats.script_types[6].count, stats.script_types[6].amount), (1, 300));
+    }
+}
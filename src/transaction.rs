@@ -1,4 +1,21 @@
-use crate::{Hasher, HashingBufferReader, TransactionOutput, TryInto};
+use crate::{
+    BufferWriter, Hasher, HashingBufferReader, ParseError, ParseErrorKind, ParseResult, ParseResultExt, TransactionOutput, TryInto,
+};
+
+/// Smallest serialized input: outpoint, empty script and sequence.
+const MIN_INPUT_SIZE: usize = 41;
+/// Smallest serialized output: value and empty script.
+const MIN_OUTPUT_SIZE: usize = 9;
+/// Highest input sequence that opts in to replacement, from BIP125.
+const MAX_BIP125_RBF_SEQUENCE: u32 = 0xFFFFFFFD;
+
+#[derive(Debug, Clone)]
+pub struct Input {
+    pub txo: TransactionOutput,
+    pub script: Vec<u8>,
+    pub sequence: u32,
+    pub witness: Vec<Vec<u8>>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Output {
@@ -9,53 +26,243 @@ pub struct Output {
 #[derive(Debug, Clone)]
 pub struct Transaction {
     pub hash: [u8; 32],
-    pub inputs: Vec<TransactionOutput>,
+    pub witness_hash: [u8; 32],
+    pub version: i32,
+    pub inputs: Vec<Input>,
     pub outputs: Vec<Output>,
+    pub locktime: u32,
+    size: usize,
+    base_size: usize,
 }
 
 impl Transaction {
-    pub fn from_slice(buffer: &[u8]) -> Self {
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].txo == TransactionOutput::new([0; 32], u32::MAX)
+    }
+
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Whether an input opts in to replacement, as defined by BIP125.
+    pub fn signals_replaceability(&self) -> bool {
+        self.inputs.iter().any(|input| input.sequence <= MAX_BIP125_RBF_SEQUENCE)
+    }
+
+    /// Size of the serialization with witness data.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Size of the serialization without witness data, the one hashed into the txid.
+    pub fn base_size(&self) -> usize {
+        self.base_size
+    }
+
+    pub fn weight(&self) -> usize {
+        self.base_size * 3 + self.size
+    }
+
+    pub fn vsize(&self) -> usize {
+        let weight = self.weight();
+        weight / 4 + (weight % 4).min(1)
+    }
+
+    /// Parses a transaction that takes the whole buffer.
+    pub fn from_slice(buffer: &[u8]) -> ParseResult<Self> {
         let mut reader = HashingBufferReader::new(buffer);
-        Self::from_reader(&mut reader)
+        let transaction = Self::from_reader(&mut reader)?;
+        reader.expect_end("transaction")?;
+        Ok(transaction)
     }
 
-    pub fn from_reader(reader: &mut HashingBufferReader) -> Self {
+    pub fn from_reader(reader: &mut HashingBufferReader) -> ParseResult<Self> {
+        let mut start = reader.clone();
         let mut hasher = Hasher::new();
-        let _version = reader.read_i32_le(&mut Some(&mut hasher));
+        let version = reader.read_i32_le(&mut Some(&mut hasher)).field("version")?;
         let mut flags = 0;
-        if reader.peek_u8() == 0 {
-            reader.skip(1, &mut None);
-            flags = reader.read_u8(&mut None);
+        if reader.peek_u8().field("input count")? == 0 {
+            let offset = reader.offset();
+            reader.skip(1, &mut None).field("witness marker")?;
+            flags = reader.read_u8(&mut None).field("witness flag")?;
+            if flags != 0x01 {
+                return Err(ParseError::new(
+                    offset + 1,
+                    "witness flag",
+                    ParseErrorKind::InvalidValue(flags.into()),
+                ));
+            }
         }
-        let count_inputs = reader.read_var_int_le(&mut Some(&mut hasher));
-        let mut inputs = Vec::with_capacity(count_inputs.try_into().unwrap());
+        let count_inputs = reader.read_count_le(MIN_INPUT_SIZE, &mut Some(&mut hasher)).field("input count")?;
+        let mut inputs = Vec::with_capacity(count_inputs);
         for _ in 0..count_inputs {
-            let hash = reader.read_hash(&mut Some(&mut hasher));
-            let index = reader.read_u32_le(&mut Some(&mut hasher));
-            let _script = reader.read_var_buffer_le(&mut Some(&mut hasher));
-            let _sequence = reader.read_u32_le(&mut Some(&mut hasher));
-            inputs.push(TransactionOutput::new(hash, index));
-        }
-        let count_outputs = reader.read_var_int_le(&mut Some(&mut hasher));
-        let mut outputs = Vec::with_capacity(count_outputs.try_into().unwrap());
+            let hash = reader.read_hash(&mut Some(&mut hasher)).field("input hash")?;
+            let index = reader.read_u32_le(&mut Some(&mut hasher)).field("input index")?;
+            let script = reader.read_var_buffer_le(&mut Some(&mut hasher)).field("input script")?;
+            let sequence = reader.read_u32_le(&mut Some(&mut hasher)).field("input sequence")?;
+            inputs.push(Input {
+                txo: TransactionOutput::new(hash, index),
+                script,
+                sequence,
+                witness: Vec::new(),
+            });
+        }
+        let count_outputs = reader
+            .read_count_le(MIN_OUTPUT_SIZE, &mut Some(&mut hasher))
+            .field("output count")?;
+        let mut outputs = Vec::with_capacity(count_outputs);
         for _ in 0..count_outputs {
-            let value = reader.read_u64_le(&mut Some(&mut hasher));
-            let script = reader.read_var_buffer_le(&mut Some(&mut hasher));
+            let value = reader.read_u64_le(&mut Some(&mut hasher)).field("output value")?;
+            let script = reader.read_var_buffer_le(&mut Some(&mut hasher)).field("output script")?;
             outputs.push(Output { value, script });
         }
-        if (flags & 0x01) != 0 {
-            for _ in 0..count_inputs {
-                let count_witnesses = reader.read_var_int_le(&mut None);
+        let witness_offset = reader.offset();
+        if flags != 0 {
+            for input in inputs.iter_mut() {
+                let count_witnesses = reader.read_count_le(1, &mut None).field("witness count")?;
                 for _ in 0..count_witnesses {
-                    reader.read_var_buffer_le(&mut None);
+                    input.witness.push(reader.read_var_buffer_le(&mut None).field("witness item")?);
                 }
             }
         }
-        let _locktime = reader.read_u32_le(&mut Some(&mut hasher));
-        Self {
-            hash: hasher.digest(),
+        let witness_size = reader.offset() - witness_offset;
+        let locktime = reader.read_u32_le(&mut Some(&mut hasher)).field("locktime")?;
+        let size = reader.offset() - start.offset();
+        let base_size = if flags != 0 { size - 2 - witness_size } else { size };
+        let hash = hasher.digest();
+        // Without witness data, the serialization hashed into the wtxid is the one hashed into the txid.
+        let witness_hash = if flags != 0 {
+            let mut hasher = Hasher::new();
+            hasher.update(start.read_buffer(size, &mut None)?);
+            hasher.digest()
+        } else {
+            hash
+        };
+        Ok(Self {
+            hash,
+            witness_hash,
+            version,
             inputs,
             outputs,
+            locktime,
+            size,
+            base_size,
+        })
+    }
+
+    /// Writes the serialization of the transaction, with witness data when it has some.
+    pub fn to_writer(&self, writer: &mut BufferWriter) {
+        let witness = self.has_witness();
+        writer.write_buffer(&self.version.to_le_bytes());
+        if witness {
+            writer.write_buffer(&[0x00, 0x01]);
+        }
+        writer.write_var_int_le(self.inputs.len().try_into().unwrap());
+        for input in &self.inputs {
+            writer.write_hash(input.txo.hash);
+            writer.write_u32(input.txo.index);
+            writer.write_var_int_le(input.script.len().try_into().unwrap());
+            writer.write_buffer(&input.script);
+            writer.write_u32(input.sequence);
         }
+        writer.write_var_int_le(self.outputs.len().try_into().unwrap());
+        for output in &self.outputs {
+            writer.write_u64(output.value);
+            writer.write_var_int_le(output.script.len().try_into().unwrap());
+            writer.write_buffer(&output.script);
+        }
+        if witness {
+            for input in &self.inputs {
+                writer.write_var_int_le(input.witness.len().try_into().unwrap());
+                for item in &input.witness {
+                    writer.write_var_int_le(item.len().try_into().unwrap());
+                    writer.write_buffer(item);
+                }
+            }
+        }
+        writer.write_u32(self.locktime);
+    }
+
+    pub fn to_buffer(&self) -> Vec<u8> {
+        let mut writer = BufferWriter::new();
+        self.to_writer(&mut writer);
+        writer.buffer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transaction;
+    use crate::{hex, Hasher, ParseError, ParseErrorKind, TransactionOutput};
+
+    const VERSION: &str = "02000000";
+    const INPUTS: &str = "0111111111111111111111111111111111111111111111111111111111111111110100000000fdffffff";
+    const OUTPUTS: &str = "01e803000000000000160014751e76e8199196d454941c45d1b3a323f1433bd6";
+    const WITNESS: &str = "0203aabbcc02ddee";
+    const LOCKTIME: &str = "10000000";
+
+    fn hash(buffer: &[u8]) -> [u8; 32] {
+        let mut hasher = Hasher::new();
+        hasher.update(buffer);
+        hasher.digest()
+    }
+
+    #[test]
+    fn from_slice() {
+        let buffer = hex::decode([VERSION, INPUTS, OUTPUTS, LOCKTIME].concat()).unwrap();
+        let transaction = Transaction::from_slice(&buffer).unwrap();
+        assert_eq!(transaction.version, 2);
+        assert_eq!(transaction.inputs[0].txo, TransactionOutput::new([0x11; 32], 1));
+        assert_eq!(transaction.inputs[0].sequence, 0xFFFFFFFD);
+        assert_eq!(transaction.outputs[0].value, 1000);
+        assert_eq!(transaction.locktime, 16);
+        assert!(transaction.signals_replaceability());
+        assert_eq!(transaction.hash, hash(&buffer));
+        assert_eq!(transaction.witness_hash, transaction.hash);
+        assert_eq!(transaction.weight(), buffer.len() * 4);
+        assert_eq!(transaction.to_buffer(), buffer);
+        let inputs = INPUTS.replace("fdffffff", "feffffff");
+        let buffer = hex::decode([VERSION, &inputs, OUTPUTS, LOCKTIME].concat()).unwrap();
+        assert!(!Transaction::from_slice(&buffer).unwrap().signals_replaceability());
+    }
+
+    #[test]
+    fn from_slice_with_witness() {
+        let base_buffer = hex::decode([VERSION, INPUTS, OUTPUTS, LOCKTIME].concat()).unwrap();
+        let buffer = hex::decode([VERSION, "0001", INPUTS, OUTPUTS, WITNESS, LOCKTIME].concat()).unwrap();
+        let transaction = Transaction::from_slice(&buffer).unwrap();
+        assert_eq!(transaction.inputs[0].witness, vec![vec![0xAA, 0xBB, 0xCC], vec![0xDD, 0xEE]]);
+        assert_eq!(transaction.hash, hash(&base_buffer));
+        assert_eq!(transaction.witness_hash, hash(&buffer));
+        assert_eq!(transaction.size(), buffer.len());
+        assert_eq!(transaction.base_size(), base_buffer.len());
+        assert_eq!(transaction.weight(), base_buffer.len() * 3 + buffer.len());
+        assert_eq!(transaction.weight(), 338);
+        assert_eq!(transaction.vsize(), 85);
+        assert_eq!(transaction.to_buffer(), buffer);
+    }
+
+    #[test]
+    fn from_slice_malformed() {
+        let buffer = hex::decode([VERSION, INPUTS, OUTPUTS].concat()).unwrap();
+        assert_eq!(
+            Transaction::from_slice(&buffer).unwrap_err(),
+            ParseError::new(buffer.len(), "locktime", ParseErrorKind::UnexpectedEnd { length: 4 })
+        );
+        let buffer = hex::decode([VERSION, "ff", INPUTS].concat()).unwrap();
+        assert_eq!(
+            Transaction::from_slice(&buffer).unwrap_err(),
+            ParseError::new(4, "input count", ParseErrorKind::InvalidValue(0x1111111111111101))
+        );
+        let buffer = hex::decode([VERSION, "0002", INPUTS, OUTPUTS, WITNESS, LOCKTIME].concat()).unwrap();
+        assert_eq!(
+            Transaction::from_slice(&buffer).unwrap_err(),
+            ParseError::new(5, "witness flag", ParseErrorKind::InvalidValue(2))
+        );
+        let buffer = hex::decode([VERSION, INPUTS, OUTPUTS, LOCKTIME, "00"].concat()).unwrap();
+        assert_eq!(
+            Transaction::from_slice(&buffer).unwrap_err(),
+            ParseError::new(buffer.len() - 1, "transaction", ParseErrorKind::TrailingBytes { length: 1 })
+        );
     }
 }
@@ -1,4 +1,4 @@
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone)]
 pub struct TransactionOutput {
     pub hash: [u8; 32],
     pub index: u32,